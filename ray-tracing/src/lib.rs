@@ -8,14 +8,77 @@ pub struct Ray {
 pub struct Wall {
     pub p1: Vector<f64>,
     pub p2: Vector<f64>,
+    pub opaque: bool,     // blocks sight
+    pub penetration: f64, // fraction of bullet damage kept when going through
+    pub damping: f64,     // fraction of sound absorbed when going through
+}
+
+/// A wall crossed by a bullet
+pub struct Hit {
+    pub index: usize,       // index of the wall
+    pub point: Vector<f64>, // where the wall was hit
+    pub distance: f64,      // distance from the origin of the ray
+    pub damage: f64,        // damage of the bullet when reaching the wall
+    pub remaining: f64,     // damage of the bullet after going through (0 if stopped)
 }
 
 impl Wall {
     pub fn new(p1: Vector<f64>, p2: Vector<f64>) -> Wall {
-        Wall { p1, p2 }
+        Wall::with_material(p1, p2, true, 0.0, 1.0)
+    }
+
+    pub fn with_material(
+        p1: Vector<f64>,
+        p2: Vector<f64>,
+        opaque: bool,
+        penetration: f64,
+        damping: f64,
+    ) -> Wall {
+        Wall {
+            p1,
+            p2,
+            opaque,
+            penetration,
+            damping,
+        }
     }
 }
 
+/// Intersection point of the segments `[a, b]` and `[c, d]` (if any)
+pub fn segments_intersect(
+    a: Vector<f64>,
+    b: Vector<f64>,
+    c: Vector<f64>,
+    d: Vector<f64>,
+) -> Option<Vector<f64>> {
+    let (rx, ry) = (b.x - a.x, b.y - a.y);
+    let (sx, sy) = (d.x - c.x, d.y - c.y);
+    let denom = rx * sy - ry * sx;
+    if denom == 0.0 {
+        return None;
+    }
+    let t = ((c.x - a.x) * sy - (c.y - a.y) * sx) / denom;
+    let u = ((c.x - a.x) * ry - (c.y - a.y) * rx) / denom;
+    if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+        Some(Vector::new(a.x + t * rx, a.y + t * ry, 0.0))
+    } else {
+        None
+    }
+}
+
+/// Fraction of a sound emitted at `from` that is heard at `to` (walls absorb some of it)
+pub fn attenuation(from: Vector<f64>, to: Vector<f64>, walls: &[Wall]) -> f64 {
+    let d = from.distance(to);
+    if d == 0.0 {
+        return 1.0;
+    }
+    let ray = Ray::new(from, (to - from) * (1.0 / d));
+    ray.trace(walls)
+        .iter()
+        .take_while(|(_, _, dist)| *dist <= d)
+        .fold(1.0, |acc, (i, _, _)| acc * (1.0 - walls[*i].damping))
+}
+
 impl Ray {
     pub fn new(origin: Vector<f64>, dir: Vector<f64>) -> Ray {
         Ray { origin, dir }
@@ -26,7 +89,7 @@ impl Ray {
         self.dir = dir.normalise();
     }
 
-    pub fn cast(&mut self, wall: &Wall) -> Option<Vector<f64>> {
+    pub fn cast(&self, wall: &Wall) -> Option<Vector<f64>> {
         let x1 = wall.p1.x;
         let y1 = wall.p1.y;
         let x2 = wall.p2.x;
@@ -53,18 +116,82 @@ impl Ray {
         None
     }
 
+    /// Closest point the ray can see (transparent walls are ignored)
     pub fn look(&mut self, walls: &Vec<Wall>) -> Option<Vector<f64>> {
+        self.look_index(walls).map(|(_, pt)| pt)
+    }
+
+    /// Same as `look` but also returns the index of the wall that was hit
+    pub fn look_index(&mut self, walls: &[Wall]) -> Option<(usize, Vector<f64>)> {
         let mut record = std::f64::MAX;
         let mut closest = None;
-        for wall in walls {
+        for (i, wall) in walls.iter().enumerate() {
+            if !wall.opaque {
+                continue;
+            }
             if let Some(pt) = self.cast(wall) {
                 let d = self.origin.distance(pt);
                 if d < record {
                     record = d;
-                    closest = Some(pt);
+                    closest = Some((i, pt));
                 }
             }
         }
         closest
     }
+
+    /// All walls crossed by the ray as `(index, point, distance)`, closest first
+    pub fn trace(&self, walls: &[Wall]) -> Vec<(usize, Vector<f64>, f64)> {
+        let mut hits = walls
+            .iter()
+            .enumerate()
+            .filter_map(|(i, w)| self.cast(w).map(|p| (i, p, self.origin.distance(p))))
+            .collect::<Vec<_>>();
+        hits.sort_by(|a, b| a.2.total_cmp(&b.2));
+        hits
+    }
+
+    /// Walls crossed by a bullet of the given damage, until it is stopped or out of range
+    pub fn penetrate(&self, walls: &[Wall], damage: f64, range: f64) -> Vec<Hit> {
+        let mut hits = Vec::new();
+        let mut damage = damage;
+        for (index, point, distance) in self.trace(walls) {
+            if distance > range || damage <= 0.0 {
+                break;
+            }
+            let remaining = damage * walls[index].penetration;
+            hits.push(Hit {
+                index,
+                point,
+                distance,
+                damage,
+                remaining,
+            });
+            damage = remaining;
+        }
+        hits
+    }
+
+    /// Distance along the ray to the first intersection with a circle (if any)
+    pub fn cast_circle(&self, center: Vector<f64>, radius: f64) -> Option<f64> {
+        let (ox, oy) = (self.origin.x - center.x, self.origin.y - center.y);
+        let (dx, dy) = (self.dir.x, self.dir.y);
+
+        let a = dx * dx + dy * dy;
+        let b = 2.0 * (ox * dx + oy * dy);
+        let c = ox * ox + oy * oy - radius * radius;
+        let delta = b * b - 4.0 * a * c;
+        if a == 0.0 || delta < 0.0 {
+            return None;
+        }
+
+        let sq = f64::sqrt(delta);
+        let t1 = (-b - sq) / (2.0 * a);
+        let t2 = (-b + sq) / (2.0 * a);
+        let t = if t1 >= 0.0 { t1 } else { t2 };
+        if t < 0.0 {
+            return None;
+        }
+        Some(t * f64::sqrt(a))
+    }
 }
@@ -0,0 +1,297 @@
+pub mod bt;
+pub mod cover;
+pub mod influence;
+pub mod perception;
+pub mod squad;
+
+use std::collections::HashMap;
+
+use math_vector::Vector;
+use rand::Rng;
+use ray_tracing::Wall as RayTracingWall;
+use serde_json::Value;
+
+use crate::{
+    bot::wander_point, enemy::Enemy, map::Map, nav::NavGrid, projectile::Target, sound::SoundEvent,
+};
+use bt::{Blackboard, Status, Task, Tree, TreeState};
+use cover::{CoverMap, GOOD_COVER};
+use influence::InfluenceMap;
+
+/// Behaviour trees of the enemy kinds, one `<kind>.json` file per kind in a folder
+#[derive(Clone, Debug, Default)]
+pub struct Behaviours {
+    pub trees: HashMap<String, Tree>,
+}
+
+/// What the enemies and the bots can know about the world this frame
+pub struct Senses<'a> {
+    pub targets: &'a [Target],            // combatants (see `Game::targets`)
+    pub own: usize,                       // index of the agent in `targets`
+    pub sounds: &'a [SoundEvent],         // sounds emitted during the frame
+    pub map: &'a Map,                     // the walls
+    pub walls: &'a [RayTracingWall],      // the walls for the sight tests (see `Wall::to_rt`)
+    pub nav: Option<&'a NavGrid>,         // for path finding (straight lines without it)
+    pub cover: Option<&'a CoverMap>,      // cover points of the map
+    pub danger: Option<&'a InfluenceMap>, // danger for the side of the agent
+}
+
+/// Behaviour tree of one enemy and its memory
+pub struct Brain {
+    pub tree: Tree,
+    pub state: TreeState,
+    pub blackboard: Blackboard,
+    pub path: Vec<Vector<f64>>, // waypoints to the destination of the current move
+}
+
+impl Behaviours {
+    pub fn new() -> Behaviours {
+        Behaviours::default()
+    }
+
+    /// Load all the trees of a folder, named after their file
+    pub fn load(dir: &str) -> Result<Behaviours, String> {
+        let entries =
+            std::fs::read_dir(dir).map_err(|e| format!("could not read {}: {}", dir, e))?;
+        let mut behaviours = Behaviours::new();
+        for path in entries.flatten().map(|e| e.path()) {
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            let tree = Tree::load(&path.to_string_lossy())?;
+            behaviours.trees.insert(name.to_string(), tree);
+        }
+        Ok(behaviours)
+    }
+
+    pub fn from_value(v: &Value) -> Result<Behaviours, String> {
+        let mut behaviours = Behaviours::new();
+        for (name, tree) in v.as_object().ok_or("behaviours should be an object")? {
+            let tree = Tree::from_value(tree).map_err(|e| format!("{}: {}", name, e))?;
+            behaviours.trees.insert(name.clone(), tree);
+        }
+        Ok(behaviours)
+    }
+
+    pub fn to_value(&self) -> Value {
+        Value::Object(
+            self.trees
+                .iter()
+                .map(|(name, tree)| (name.clone(), tree.to_value()))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Tree> {
+        self.trees.get(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trees.is_empty()
+    }
+}
+
+impl Senses<'_> {
+    /// Path on the walkable grid, around the dangerous cells if the danger is known
+    pub fn find_path(&self, from: Vector<f64>, to: Vector<f64>) -> Option<Vec<Vector<f64>>> {
+        let nav = self.nav?;
+        match self.danger {
+            Some(danger) => nav.find_path_with(from, to, |i| danger.cost(nav, i)),
+            None => nav.find_path(from, to),
+        }
+    }
+}
+
+impl Brain {
+    pub fn new(tree: &Tree) -> Brain {
+        Brain {
+            tree: tree.clone(),
+            state: TreeState::default(),
+            blackboard: Blackboard::new(),
+            path: Vec::new(),
+        }
+    }
+
+    /// Runs the tree for one frame, returns the direction of the shot fired (if any)
+    pub fn tick(
+        &mut self,
+        enemy: &mut Enemy,
+        senses: &Senses,
+        rng: &mut impl Rng,
+    ) -> Option<Vector<f64>> {
+        let p = &mut enemy.perception;
+        p.update(
+            enemy.pos,
+            senses.own,
+            senses.targets,
+            senses.sounds,
+            senses.map,
+            senses.walls,
+        );
+        // what the enemy perceives is on the blackboard
+        let bb = &mut self.blackboard;
+        match p.memory {
+            Some(m) => bb.set("last_seen", m.pos),
+            None => bb.remove("last_seen"),
+        }
+        if let Some(noise) = p.noise {
+            bb.set("noise", noise);
+        }
+
+        let mut shot = None;
+        let path = &mut self.path;
+        self.tree
+            .tick(&mut self.state, &mut self.blackboard, |task, bb| {
+                run(task, bb, enemy, path, senses, rng, &mut shot)
+            });
+        shot
+    }
+}
+
+fn status(ok: bool) -> Status {
+    if ok {
+        Status::Success
+    } else {
+        Status::Failure
+    }
+}
+
+/// What the tasks do for an enemy
+fn run(
+    task: &Task,
+    bb: &mut Blackboard,
+    enemy: &mut Enemy,
+    path: &mut Vec<Vector<f64>>,
+    senses: &Senses,
+    rng: &mut impl Rng,
+    shot: &mut Option<Vector<f64>>,
+) -> Status {
+    match task {
+        Task::SeeTarget => match enemy.perception.target {
+            Some(pos) => {
+                bb.set("target", pos);
+                Status::Success
+            }
+            None => {
+                bb.remove("target");
+                Status::Failure
+            }
+        },
+        Task::Heard => status(enemy.perception.noise.is_some()),
+        Task::IsHurt(fraction) => status(enemy.health < fraction * enemy.max_health),
+        Task::Has(key) => status(bb.has(key)),
+        Task::Forget(key) => {
+            bb.remove(key);
+            if key == "last_seen" {
+                enemy.perception.memory = None; // or it comes back next frame
+            }
+            Status::Success
+        }
+        Task::MoveTo(key) => match bb.get(key) {
+            Some(dest) => walk(enemy, path, dest, senses),
+            None => Status::Failure,
+        },
+        Task::Wander => {
+            let dest = match bb.get("wander") {
+                Some(dest) if dest.distance(enemy.pos) > enemy.radius * 2.0 => dest,
+                _ => {
+                    let dest = wander_point(senses.map, enemy.pos, rng);
+                    bb.set("wander", dest);
+                    dest
+                }
+            };
+            walk(enemy, path, dest, senses);
+            Status::Running
+        }
+        Task::KeepDistance(distance) => match bb.get("target") {
+            Some(target) if target.distance(enemy.pos) < *distance => {
+                let away = enemy.pos + (enemy.pos - target).normalise() * enemy.speed;
+                enemy.step_towards(away, senses.map);
+                enemy.turn_towards(target);
+                Status::Running
+            }
+            Some(_) => Status::Success,
+            None => Status::Failure,
+        },
+        Task::Shoot => match bb.get("target") {
+            None => Status::Failure,
+            Some(target) if target.distance(enemy.pos) > enemy.kind.range() => Status::Failure,
+            Some(target) if !enemy.turn_towards(target) || enemy.fire_cooldown > 0 => {
+                Status::Running
+            }
+            Some(target) => {
+                let s = enemy.kind.spread();
+                *shot = Some((target - enemy.pos).rotate_z(rng.gen_range(-s..=s)));
+                enemy.fire_cooldown = enemy.kind.fire_rate();
+                Status::Success
+            }
+        },
+        Task::TakeCover => {
+            let threat = match bb.get("target").or(bb.get("last_seen")) {
+                Some(threat) => threat,
+                None => return Status::Failure,
+            };
+            let cover = match senses.cover {
+                Some(cover) => cover,
+                None => return Status::Failure,
+            };
+            // the current spot as long as it still hides
+            let current = bb
+                .get("cover")
+                .and_then(|pos| cover.points.iter().find(|p| p.pos.distance(pos) < 1.0))
+                .filter(|p| cover.rate(p, threat, senses.walls) >= GOOD_COVER);
+            let dest = match current {
+                Some(p) => p.pos,
+                None => match cover.best(enemy.pos, threat, 300.0, senses.walls) {
+                    Some((i, _)) => cover.points[i].pos,
+                    None => return Status::Failure,
+                },
+            };
+            bb.set("cover", dest);
+            walk(enemy, path, dest, senses)
+        }
+        Task::Role(role) => status(enemy.role == *role),
+        Task::Suppress => match bb.get("last_seen") {
+            None => Status::Failure,
+            Some(target) => {
+                let ready =
+                    enemy.fire_cooldown == 0 && target.distance(enemy.pos) <= enemy.kind.range();
+                if enemy.turn_towards(target) && ready {
+                    // not aimed: the point is to keep the target behind its cover
+                    let s = enemy.kind.spread() * 2.0;
+                    *shot = Some((target - enemy.pos).rotate_z(rng.gen_range(-s..=s)));
+                    enemy.fire_cooldown = enemy.kind.fire_rate();
+                }
+                Status::Running
+            }
+        },
+        Task::Wait(_) => Status::Success, // done by the tree
+    }
+}
+
+/// One step on the way to `dest`, success once there
+fn walk(
+    enemy: &mut Enemy,
+    path: &mut Vec<Vector<f64>>,
+    dest: Vector<f64>,
+    senses: &Senses,
+) -> Status {
+    if dest.distance(enemy.pos) <= enemy.radius {
+        path.clear();
+        return Status::Success;
+    }
+    // a moving destination (a target) only changes the path once it moved by a cell or so
+    if path.last().is_none_or(|last| last.distance(dest) > 20.0) {
+        *path = senses
+            .find_path(enemy.pos, dest)
+            .unwrap_or_else(|| vec![dest]);
+    }
+    while path.len() > 1 && path[0].distance(enemy.pos) < enemy.radius {
+        path.remove(0);
+    }
+    let next = if path.len() > 1 { path[0] } else { dest };
+    enemy.step_towards(next, senses.map);
+    enemy.turn_towards(next);
+    Status::Running
+}
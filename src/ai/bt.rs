@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+
+use math_vector::Vector;
+use serde_json::{json, Value};
+
+use super::squad::Role;
+
+/// Result of ticking a node
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    Success,
+    Failure,
+    Running, // not done yet, ticked again next frame
+}
+
+/// Leaf of a tree: a check or an action of the agent
+#[derive(Clone, Debug, PartialEq)]
+pub enum Task {
+    SeeTarget,         // an opponent is detected and in sight (stored as "target")
+    Heard,             // a sound was heard this frame (the last one is "noise")
+    IsHurt(f64),       // health below a fraction of the max health
+    Has(String),       // a position is on the blackboard
+    Forget(String),    // removes a position from the blackboard
+    MoveTo(String),    // walks to a position of the blackboard
+    Wander,            // walks to places of the map picked at random
+    KeepDistance(f64), // backs away from the target when closer than that
+    Shoot,             // fires at the target when it is in range and the weapon is ready
+    Wait(u64),         // does nothing for a number of frames
+    Role(Role),        // the squad gave it that role
+    Suppress,          // shoots towards "last_seen" (even without seeing the target)
+    TakeCover,         // goes to the best cover point against the target (stored as "cover")
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeKind {
+    Selector(Vec<Node>),      // first child that does not fail
+    Sequence(Vec<Node>),      // children in order, as long as they succeed
+    Invert(Box<Node>),        // success and failure swapped
+    Succeed(Box<Node>),       // success whatever the child does (unless running)
+    Cooldown(u64, Box<Node>), // fails for a number of frames after the child succeeded
+    Task(Task),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub name: String, // shown by the debug overlay
+    pub kind: NodeKind,
+    id: usize, // index of the node in the state of the tree
+}
+
+/// Behaviour tree, shared by all the agents of a kind (each one has its own `TreeState`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tree {
+    pub root: Node,
+    pub size: usize, // number of nodes
+}
+
+/// What an agent running a tree remembers between frames
+#[derive(Clone, Debug, Default)]
+pub struct TreeState {
+    pub frame: u64,     // number of ticks
+    pub active: String, // name of the last leaf that ran (debug overlay)
+    timers: Vec<u64>,   // frame at which waits and cooldowns end (per node)
+    ticked: Vec<u64>,   // last frame each node was ticked
+}
+
+/// Positions the nodes of a tree share ("last_seen" is kept by the memory of the agent)
+#[derive(Clone, Debug, Default)]
+pub struct Blackboard {
+    positions: HashMap<String, Vector<f64>>,
+}
+
+impl Task {
+    pub fn from_value(name: &str, v: &Value) -> Result<Task, String> {
+        let key = || {
+            v["key"]
+                .as_str()
+                .map(|s| s.to_string())
+                .ok_or(format!("missing key of {}", name))
+        };
+        let number = |field: &str| {
+            v[field]
+                .as_f64()
+                .ok_or(format!("missing {} of {}", field, name))
+        };
+        Ok(match name {
+            "see_target" => Task::SeeTarget,
+            "heard" => Task::Heard,
+            "is_hurt" => Task::IsHurt(number("below")?),
+            "has" => Task::Has(key()?),
+            "forget" => Task::Forget(key()?),
+            "move_to" => Task::MoveTo(key()?),
+            "wander" => Task::Wander,
+            "keep_distance" => Task::KeepDistance(number("distance")?),
+            "shoot" => Task::Shoot,
+            "wait" => Task::Wait(number("frames")? as u64),
+            "role" => {
+                let role = v["role"].as_str().ok_or("missing role of role")?;
+                Task::Role(Role::from_name(role).ok_or(format!("unknown role {}", role))?)
+            }
+            "suppress" => Task::Suppress,
+            "take_cover" => Task::TakeCover,
+            _ => return Err(format!("unknown node type {}", name)),
+        })
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            Task::SeeTarget => json!({ "type": "see_target" }),
+            Task::Heard => json!({ "type": "heard" }),
+            Task::IsHurt(f) => json!({ "type": "is_hurt", "below": f }),
+            Task::Has(k) => json!({ "type": "has", "key": k }),
+            Task::Forget(k) => json!({ "type": "forget", "key": k }),
+            Task::MoveTo(k) => json!({ "type": "move_to", "key": k }),
+            Task::Wander => json!({ "type": "wander" }),
+            Task::KeepDistance(d) => json!({ "type": "keep_distance", "distance": d }),
+            Task::Shoot => json!({ "type": "shoot" }),
+            Task::Wait(n) => json!({ "type": "wait", "frames": n }),
+            Task::Role(r) => json!({ "type": "role", "role": r.name() }),
+            Task::Suppress => json!({ "type": "suppress" }),
+            Task::TakeCover => json!({ "type": "take_cover" }),
+        }
+    }
+}
+
+impl Node {
+    /// Parse a node and its children, numbering them from `next_id`
+    fn from_value(v: &Value, next_id: &mut usize) -> Result<Node, String> {
+        let id = *next_id;
+        *next_id += 1;
+        let kind_name = v["type"].as_str().ok_or("missing node type")?;
+        let child = |next_id: &mut usize| match v.get("child") {
+            Some(c) => Node::from_value(c, next_id).map(Box::new),
+            None => Err(format!("missing child of {}", kind_name)),
+        };
+        let children = |next_id: &mut usize| {
+            let list = v["children"]
+                .as_array()
+                .ok_or(format!("missing children of {}", kind_name))?;
+            if list.is_empty() {
+                return Err(format!("{} without children", kind_name));
+            }
+            list.iter()
+                .map(|c| Node::from_value(c, next_id))
+                .collect::<Result<Vec<Node>, String>>()
+        };
+        let kind = match kind_name {
+            "selector" => NodeKind::Selector(children(next_id)?),
+            "sequence" => NodeKind::Sequence(children(next_id)?),
+            "invert" => NodeKind::Invert(child(next_id)?),
+            "succeed" => NodeKind::Succeed(child(next_id)?),
+            "cooldown" => {
+                let frames = v["frames"].as_u64().ok_or("missing frames of cooldown")?;
+                NodeKind::Cooldown(frames, child(next_id)?)
+            }
+            name => NodeKind::Task(Task::from_value(name, v)?),
+        };
+        Ok(Node {
+            name: v["name"].as_str().unwrap_or(kind_name).to_string(),
+            kind,
+            id,
+        })
+    }
+
+    fn to_value(&self) -> Value {
+        let mut v = match &self.kind {
+            NodeKind::Selector(c) => json!({ "type": "selector", "children": to_values(c) }),
+            NodeKind::Sequence(c) => json!({ "type": "sequence", "children": to_values(c) }),
+            NodeKind::Invert(c) => json!({ "type": "invert", "child": c.to_value() }),
+            NodeKind::Succeed(c) => json!({ "type": "succeed", "child": c.to_value() }),
+            NodeKind::Cooldown(n, c) => {
+                json!({ "type": "cooldown", "frames": n, "child": c.to_value() })
+            }
+            NodeKind::Task(t) => t.to_value(),
+        };
+        if v["type"].as_str() != Some(&self.name) {
+            v["name"] = json!(self.name);
+        }
+        v
+    }
+
+    /// Runs the node for one frame, `run` does the tasks
+    fn tick<F>(&self, state: &mut TreeState, bb: &mut Blackboard, run: &mut F) -> Status
+    where
+        F: FnMut(&Task, &mut Blackboard) -> Status,
+    {
+        let frame = state.frame;
+        // a node that was not ticked on the previous frame starts over
+        let resumed = state.ticked[self.id] > 0 && state.ticked[self.id] + 1 == frame;
+        state.ticked[self.id] = frame;
+
+        match &self.kind {
+            NodeKind::Selector(children) => {
+                for c in children {
+                    match c.tick(state, bb, run) {
+                        Status::Failure => continue,
+                        status => return status,
+                    }
+                }
+                Status::Failure
+            }
+            NodeKind::Sequence(children) => {
+                for c in children {
+                    match c.tick(state, bb, run) {
+                        Status::Success => continue,
+                        status => return status,
+                    }
+                }
+                Status::Success
+            }
+            NodeKind::Invert(c) => match c.tick(state, bb, run) {
+                Status::Success => Status::Failure,
+                Status::Failure => Status::Success,
+                Status::Running => Status::Running,
+            },
+            NodeKind::Succeed(c) => match c.tick(state, bb, run) {
+                Status::Running => Status::Running,
+                _ => Status::Success,
+            },
+            NodeKind::Cooldown(frames, c) => {
+                if frame < state.timers[self.id] {
+                    return Status::Failure;
+                }
+                let status = c.tick(state, bb, run);
+                if status == Status::Success {
+                    state.timers[self.id] = frame + frames;
+                }
+                status
+            }
+            NodeKind::Task(Task::Wait(frames)) => {
+                state.active = self.name.clone();
+                if !resumed {
+                    state.timers[self.id] = frame + frames;
+                }
+                if frame >= state.timers[self.id] {
+                    state.ticked[self.id] = 0; // the next wait is a new one
+                    Status::Success
+                } else {
+                    Status::Running
+                }
+            }
+            NodeKind::Task(task) => {
+                state.active = self.name.clone();
+                run(task, bb)
+            }
+        }
+    }
+}
+
+fn to_values(nodes: &[Node]) -> Vec<Value> {
+    nodes.iter().map(|n| n.to_value()).collect()
+}
+
+impl Tree {
+    pub fn from_value(v: &Value) -> Result<Tree, String> {
+        let mut size = 0;
+        let root = Node::from_value(&v["root"], &mut size)?;
+        Ok(Tree { root, size })
+    }
+
+    pub fn to_value(&self) -> Value {
+        json!({ "root": self.root.to_value() })
+    }
+
+    pub fn load(path: &str) -> Result<Tree, String> {
+        let data =
+            std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+        let v: Value = serde_json::from_str(&data).map_err(|e| format!("{}: {}", path, e))?;
+        Tree::from_value(&v).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Runs the tree for one frame from the root, `run` does the tasks
+    pub fn tick<F>(&self, state: &mut TreeState, bb: &mut Blackboard, mut run: F) -> Status
+    where
+        F: FnMut(&Task, &mut Blackboard) -> Status,
+    {
+        if state.timers.len() != self.size {
+            state.timers = vec![0; self.size];
+            state.ticked = vec![0; self.size];
+        }
+        state.frame += 1;
+        self.root.tick(state, bb, &mut run)
+    }
+}
+
+impl Blackboard {
+    pub fn new() -> Blackboard {
+        Blackboard::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vector<f64>> {
+        self.positions.get(key).copied()
+    }
+
+    pub fn set(&mut self, key: &str, pos: Vector<f64>) {
+        self.positions.insert(key.to_string(), pos);
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.positions.remove(key);
+    }
+
+    pub fn has(&self, key: &str) -> bool {
+        self.positions.contains_key(key)
+    }
+}
@@ -0,0 +1,118 @@
+use math_vector::Vector;
+use piston_window::*;
+use ray_tracing::{Ray as RayTracingRay, Wall as RayTracingWall};
+
+use crate::{map::Map, PI};
+
+/// Distance between two cover points along a wall
+const SPACING: f64 = 30.0;
+/// Rating from which a point is worth hiding behind
+pub const GOOD_COVER: f64 = 0.5;
+
+/// Spot next to a wall that hides whoever stands there from one side
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CoverPoint {
+    pub pos: Vector<f64>,
+    pub normal: Vector<f64>, // from the spot to the wall: the direction it protects from
+    pub wall: usize,         // index of the wall in the map
+}
+
+/// Cover points of a map, built from the walls that block the sight
+pub struct CoverMap {
+    pub points: Vec<CoverPoint>,
+    pub radius: f64, // size of the agents taking cover
+}
+
+impl CoverMap {
+    /// Points on both sides of the walls, where an agent of size `radius` can stand
+    pub fn new(map: &Map, radius: f64) -> CoverMap {
+        let mut points: Vec<CoverPoint> = Vec::new();
+        for (i, w) in map.walls.iter().enumerate() {
+            let len = w.p1.distance(w.p2);
+            if !w.blocks_sight() || len < radius {
+                continue;
+            }
+            let dir = (w.p2 - w.p1).normalise();
+            let normal = dir.rotate_z(PI / 2.0);
+            let count = f64::max((len / SPACING).floor(), 1.0);
+            for k in 0..count as usize {
+                let along = w.p1 + dir * ((k as f64 + 0.5) * len / count);
+                for side in [-1.0, 1.0] {
+                    let pos = along + normal * (side * (radius + 2.0));
+                    // corners: the spot may already come from the other wall
+                    let taken = points.iter().any(|p| p.pos.distance(pos) < SPACING / 2.0);
+                    if !taken && map.is_free(pos, radius) {
+                        points.push(CoverPoint {
+                            pos,
+                            normal: normal * -side,
+                            wall: i,
+                        });
+                    }
+                }
+            }
+        }
+        CoverMap { points, radius }
+    }
+
+    /// How well a point hides from a threat, between 0 (in the open) and 1
+    /// (rays from the threat to the middle and both sides of the agent, and whether the wall faces it)
+    pub fn rate(&self, point: &CoverPoint, threat: Vector<f64>, walls: &[RayTracingWall]) -> f64 {
+        let to = point.pos - threat;
+        let d = to.length_squared().sqrt();
+        if d < 1.0 {
+            return 0.0;
+        }
+        let side = to.normalise().rotate_z(PI / 2.0) * self.radius;
+        let blocked = [point.pos, point.pos + side, point.pos - side]
+            .iter()
+            .filter(|&&p| {
+                let mut ray = RayTracingRay::new(threat, p - threat);
+                ray.look_index(walls)
+                    .is_some_and(|(_, hit)| hit.distance(threat) < p.distance(threat) - 1.0)
+            })
+            .count();
+        let dir = to.normalise();
+        let facing = -(point.normal.x * dir.x + point.normal.y * dir.y); // 1 when the wall is between
+        blocked as f64 / 3.0 * (0.5 + 0.5 * facing.max(0.0))
+    }
+
+    /// Best cover point against a threat within `max_distance` of `from` (closer is better)
+    pub fn best(
+        &self,
+        from: Vector<f64>,
+        threat: Vector<f64>,
+        max_distance: f64,
+        walls: &[RayTracingWall],
+    ) -> Option<(usize, f64)> {
+        self.points
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.pos.distance(from) <= max_distance)
+            .map(|(i, p)| (i, self.rate(p, threat, walls)))
+            .filter(|&(_, rating)| rating >= GOOD_COVER)
+            .max_by(|a, b| {
+                let score = |&(i, rating): &(usize, f64)| {
+                    rating - 0.5 * self.points[i].pos.distance(from) / max_distance
+                };
+                score(a).total_cmp(&score(b))
+            })
+    }
+
+    /// Cover points, greener when they hide well from the threat (debug overlay)
+    pub fn draw(
+        &self,
+        _c: &Context,
+        g: &mut G2d,
+        transform: math::Matrix2d,
+        threat: Vector<f64>,
+        walls: &[RayTracingWall],
+    ) {
+        for p in self.points.iter() {
+            let rating = self.rate(p, threat, walls) as f32;
+            let col = [1.0 - rating, rating, 0.2, 0.8];
+            rectangle(col, [p.pos.x - 2.0, p.pos.y - 2.0, 4.0, 4.0], transform, g);
+            let n = p.pos + p.normal * 6.0;
+            line(col, 0.5, [p.pos.x, p.pos.y, n.x, n.y], transform, g);
+        }
+    }
+}
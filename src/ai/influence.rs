@@ -0,0 +1,159 @@
+use math_vector::Vector;
+use piston_window::*;
+use ray_tracing::{Ray as RayTracingRay, Wall as RayTracingWall};
+
+use crate::{
+    nav::NavGrid,
+    projectile::Owner,
+    sound::{SoundEvent, SoundKind},
+};
+
+/// Cells whose exposure is computed again each frame (the whole grid takes a few frames)
+const CELLS_PER_FRAME: usize = 150;
+/// Distance from which a threat is not a danger anymore
+const THREAT_RANGE: f64 = 500.0;
+/// Frames for the recent damage to be forgotten
+const DAMAGE_FADE: f64 = 600.0;
+/// Frames for the fighting noise to be forgotten
+const SOUND_FADE: f64 = 240.0;
+/// Radius around a damage or a sound that it marks
+const SPREAD: f64 = 60.0;
+/// Extra cost of walking through a fully dangerous cell (in cells)
+const DANGER_COST: f64 = 6.0;
+
+/// Danger of each cell of the walkable grid for one side, from what that side knows
+pub struct InfluenceMap {
+    pub side: Owner,               // the side in danger
+    pub exposure: Vec<f64>,        // visibility from the known threats, closer is higher (0 to 1)
+    pub damage: Vec<f64>,          // damage taken around the cell recently (0 to 1)
+    pub sound: Vec<f64>,           // fighting heard around the cell recently (0 to 1)
+    pub threats: Vec<Vector<f64>>, // known positions of the opponents
+    next: usize,                   // next cell whose exposure is computed
+}
+
+impl InfluenceMap {
+    pub fn new(side: Owner, nav: &NavGrid) -> InfluenceMap {
+        let n = nav.width * nav.height;
+        InfluenceMap {
+            side,
+            exposure: vec![0.0; n],
+            damage: vec![0.0; n],
+            sound: vec![0.0; n],
+            threats: Vec::new(),
+            next: 0,
+        }
+    }
+
+    /// One frame: new threats and sounds, the old ones fade and part of the exposure is computed again
+    pub fn update(
+        &mut self,
+        nav: &NavGrid,
+        threats: &[Vector<f64>],
+        sounds: &[SoundEvent],
+        walls: &[RayTracingWall],
+    ) {
+        // threats close to each other count once
+        self.threats.clear();
+        for &t in threats {
+            if self.threats.iter().all(|k| k.distance(t) > nav.cell) {
+                self.threats.push(t);
+            }
+        }
+
+        for d in self.damage.iter_mut() {
+            *d = f64::max(*d - 1.0 / DAMAGE_FADE, 0.0);
+        }
+        for s in self.sound.iter_mut() {
+            *s = f64::max(*s - 1.0 / SOUND_FADE, 0.0);
+        }
+        for s in sounds {
+            let loudness = s.range / SoundKind::Explosion.range();
+            splat(&mut self.sound, nav, s.pos, loudness);
+        }
+
+        let n = self.exposure.len();
+        for _ in 0..CELLS_PER_FRAME.min(n) {
+            let i = self.next;
+            self.next = (i + 1) % n;
+            self.exposure[i] = if nav.walkable[i] {
+                self.exposure_at(nav.center(i % nav.width, i / nav.width), walls)
+            } else {
+                0.0
+            };
+        }
+    }
+
+    /// Damage taken by the side at a position
+    pub fn add_damage(&mut self, nav: &NavGrid, pos: Vector<f64>, amount: f64) {
+        splat(&mut self.damage, nav, pos, amount / 50.0);
+    }
+
+    /// How visible a position is from the known threats: 1 right next to one in the open, 0 when hidden
+    pub fn exposure_at(&self, pos: Vector<f64>, walls: &[RayTracingWall]) -> f64 {
+        self.threats
+            .iter()
+            .filter(|t| t.distance(pos) < THREAT_RANGE)
+            .filter(|&&t| {
+                let d = t.distance(pos);
+                let mut ray = RayTracingRay::new(t, pos - t);
+                d < 1.0
+                    || ray
+                        .look_index(walls)
+                        .is_none_or(|(_, hit)| hit.distance(t) >= d)
+            })
+            .map(|t| 1.0 - t.distance(pos) / THREAT_RANGE)
+            .fold(0.0, f64::max)
+    }
+
+    /// Danger of a cell (index in the grid) between 0 and 1
+    pub fn danger(&self, i: usize) -> f64 {
+        match (self.exposure.get(i), self.damage.get(i), self.sound.get(i)) {
+            (Some(e), Some(d), Some(s)) => f64::min(e + d + 0.5 * s, 1.0),
+            _ => 0.0,
+        }
+    }
+
+    /// Extra cost of a cell for the path finding (see `NavGrid::find_path_with`)
+    pub fn cost(&self, nav: &NavGrid, i: usize) -> f64 {
+        DANGER_COST * nav.cell * self.danger(i)
+    }
+
+    /// Heatmap of the danger, red where it is high (debug overlay)
+    pub fn draw(&self, _c: &Context, g: &mut G2d, transform: math::Matrix2d, nav: &NavGrid) {
+        for i in 0..self.exposure.len() {
+            let danger = self.danger(i) as f32;
+            if danger <= 0.01 {
+                continue;
+            }
+            let c = nav.center(i % nav.width, i / nav.width);
+            let half = nav.cell / 2.0;
+            rectangle(
+                [1.0, 1.0 - danger, 0.0, 0.4 * danger],
+                [c.x - half, c.y - half, nav.cell, nav.cell],
+                transform,
+                g,
+            );
+        }
+    }
+}
+
+/// Adds `strength` to the cells around a position, less and less away from it (values stay below 1)
+fn splat(values: &mut [f64], nav: &NavGrid, pos: Vector<f64>, strength: f64) {
+    let r = (SPREAD / nav.cell).ceil() as i64;
+    let (cx, cy) = match nav.cell_of(pos) {
+        Some((x, y)) => (x as i64, y as i64),
+        None => return,
+    };
+    for y in (cy - r).max(0)..=(cy + r).min(nav.height as i64 - 1) {
+        for x in (cx - r).max(0)..=(cx + r).min(nav.width as i64 - 1) {
+            let (x, y) = (x as usize, y as usize);
+            let d = nav.center(x, y).distance(pos);
+            if d < SPREAD {
+                let i = y * nav.width + x;
+                if let Some(v) = values.get_mut(i) {
+                    *v = f64::min(*v + strength * (1.0 - d / SPREAD), 1.0);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use math_vector::Vector;
+use piston_window::*;
+use ray_tracing::{Ray as RayTracingRay, Wall as RayTracingWall};
+
+use crate::{
+    map::Map,
+    projectile::{Owner, Target},
+    sound::SoundEvent,
+};
+
+/// Frames to detect an opponent right next to the enemy
+const DETECT_NEAR: f64 = 8.0;
+/// Frames to detect an opponent at the edge of the view
+const DETECT_FAR: f64 = 60.0;
+/// Frames for the awareness to go back to zero once nothing is in sight
+const AWARENESS_FADE: f64 = 180.0;
+/// Frames the last known position of a target is remembered
+const MEMORY_TIME: f64 = 600.0;
+
+/// Last known position of an opponent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Memory {
+    pub pos: Vector<f64>,
+    pub confidence: f64, // 1 when just seen, forgotten at 0
+}
+
+/// Senses of an enemy: a sight cone like the player's, hearing and memory
+#[derive(Clone, Debug)]
+pub struct Perception {
+    pub heading: Vector<f64>,        // where the enemy looks
+    pub fov: f64,                    // width of the sight cone (radians)
+    pub view_radius: f64,            // how far it sees
+    pub hearing: f64,                // quietest sound it reacts to
+    pub awareness: f64,              // detection of what is in sight, detected at 1
+    pub target: Option<Vector<f64>>, // opponent detected and in sight this frame
+    pub memory: Option<Memory>,      // last known position of the target
+    pub noise: Option<Vector<f64>>,  // loudest sound heard this frame
+}
+
+impl Perception {
+    pub fn new(fov: f64, view_radius: f64) -> Perception {
+        Perception {
+            heading: Vector::new(0.0, 1.0, 0.0),
+            fov,
+            view_radius,
+            hearing: 0.1,
+            awareness: 0.0,
+            target: None,
+            memory: None,
+            noise: None,
+        }
+    }
+
+    /// Looks and listens for one frame from `pos` (`own`: index of the enemy in `targets`)
+    pub fn update(
+        &mut self,
+        pos: Vector<f64>,
+        own: usize,
+        targets: &[Target],
+        sounds: &[SoundEvent],
+        map: &Map,
+        walls: &[RayTracingWall],
+    ) {
+        // sounds: not its own shots, and a loud one puts it on guard
+        let heard = sounds
+            .iter()
+            .filter(|s| s.emitter != Some(own))
+            .map(|s| (s.pos, s.volume_at(map, pos)))
+            .filter(|&(_, volume)| volume >= self.hearing)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        self.noise = heard.map(|(p, _)| p);
+        if let Some((_, volume)) = heard {
+            self.awareness = f64::max(self.awareness, 0.5 * volume);
+        }
+
+        let visible = targets
+            .iter()
+            .filter(|t| t.side == Owner::Player && self.can_see(pos, t, walls))
+            .map(|t| (t.pos, t.pos.distance(pos)))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+
+        self.target = None;
+        match visible {
+            Some((target, d)) => {
+                // the closer the faster
+                let frames = DETECT_NEAR + (DETECT_FAR - DETECT_NEAR) * d / self.view_radius;
+                self.awareness = f64::min(self.awareness + 1.0 / frames, 1.0);
+                if self.awareness >= 1.0 {
+                    self.target = Some(target);
+                    self.memory = Some(Memory {
+                        pos: target,
+                        confidence: 1.0,
+                    });
+                }
+            }
+            None => {
+                self.awareness = f64::max(self.awareness - 1.0 / AWARENESS_FADE, 0.0);
+                if let Some(m) = self.memory.as_mut() {
+                    m.confidence -= 1.0 / MEMORY_TIME;
+                }
+                if self.memory.is_some_and(|m| m.confidence <= 0.0) {
+                    self.memory = None;
+                }
+            }
+        }
+    }
+
+    /// Whether a target is in the sight cone and no opaque wall is in the way
+    fn can_see(&self, pos: Vector<f64>, target: &Target, walls: &[RayTracingWall]) -> bool {
+        let to = target.pos - pos;
+        let d = to.length_squared().sqrt();
+        if d > self.view_radius + target.radius {
+            return false;
+        }
+        if d > target.radius {
+            let (h, dir) = (self.heading.normalise(), to.normalise());
+            let angle = f64::acos((h.x * dir.x + h.y * dir.y).clamp(-1.0, 1.0));
+            // the edge of the circle is enough
+            if angle > self.fov / 2.0 + f64::asin(target.radius / d) {
+                return false;
+            }
+        }
+        let mut ray = RayTracingRay::new(pos, to);
+        ray.look_index(walls)
+            .is_none_or(|(_, p)| p.distance(pos) >= d - target.radius)
+    }
+
+    /// Sight cone, awareness and memory (debug overlay)
+    pub fn draw(&self, _c: &Context, g: &mut G2d, transform: math::Matrix2d, pos: Vector<f64>) {
+        let col = [1.0, 1.0 - self.awareness as f32, 0.2, 0.5];
+        let h = self.heading.normalise() * self.view_radius;
+        for side in [-0.5, 0.5] {
+            let p = pos + h.rotate_z(self.fov * side);
+            line(col, 0.5, [pos.x, pos.y, p.x, p.y], transform, g);
+        }
+        if let Some(m) = self.memory {
+            let col = [1.0, 0.2, 0.2, m.confidence as f32];
+            let r = 4.0;
+            line(
+                col,
+                1.0,
+                [m.pos.x - r, m.pos.y - r, m.pos.x + r, m.pos.y + r],
+                transform,
+                g,
+            );
+            line(
+                col,
+                1.0,
+                [m.pos.x - r, m.pos.y + r, m.pos.x + r, m.pos.y - r],
+                transform,
+                g,
+            );
+        }
+    }
+}
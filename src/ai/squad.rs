@@ -0,0 +1,189 @@
+use math_vector::Vector;
+
+use super::perception::Memory;
+use crate::{enemy::Enemy, nav::NavGrid};
+
+/// Most enemies in a squad
+pub const SQUAD_SIZE: usize = 3;
+/// Enemies this close to a squad join it
+const JOIN_DISTANCE: f64 = 300.0;
+/// Members further than that from the others regroup
+const REGROUP_DISTANCE: f64 = 150.0;
+/// Frames between two changes of the member giving suppressing fire
+const BOUND_TIME: u64 = 180;
+/// Frames between two updates of the flanking paths
+const PATH_TIME: u64 = 60;
+/// Half width of the corridor along the direct line that flanking paths stay out of
+const CORRIDOR: f64 = 60.0;
+
+/// What a member does for its squad (its behaviour tree decides how)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    Free,     // nothing asked: its own behaviour
+    Suppress, // shoots at the last known position of the target while the others advance
+    Flank,    // goes around the walls to the target on the path of the squad ("flank")
+    Regroup,  // goes back to the others ("rally")
+}
+
+/// Group of enemies sharing what they know and splitting the work
+pub struct Squad {
+    pub id: usize,
+    pub last_seen: Option<Memory>, // what the best informed member knows of the target
+    pub rally: Vector<f64>,        // where the members regroup (on the first one)
+    pub suppressor: usize,         // member giving suppressing fire (index in the members)
+    timer: u64,                    // frames since the squad was formed
+}
+
+impl Role {
+    pub fn from_name(name: &str) -> Option<Role> {
+        match name {
+            "free" => Some(Role::Free),
+            "suppress" => Some(Role::Suppress),
+            "flank" => Some(Role::Flank),
+            "regroup" => Some(Role::Regroup),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Role::Free => "free",
+            Role::Suppress => "suppress",
+            Role::Flank => "flank",
+            Role::Regroup => "regroup",
+        }
+    }
+}
+
+impl Squad {
+    pub fn new(id: usize, pos: Vector<f64>) -> Squad {
+        Squad {
+            id,
+            last_seen: None,
+            rally: pos,
+            suppressor: 0,
+            timer: 0,
+        }
+    }
+
+    /// Shares the knowledge of the members and gives them a role
+    pub fn update(&mut self, members: &mut [&mut Enemy], nav: Option<&NavGrid>) {
+        self.timer += 1;
+        if members.is_empty() {
+            return;
+        }
+        self.rally = members[0].pos;
+
+        // the freshest memory goes to everybody
+        self.last_seen = members
+            .iter()
+            .filter_map(|e| e.perception.memory)
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence));
+        for e in members.iter_mut() {
+            let own = e.perception.memory.map_or(0.0, |m| m.confidence);
+            if self.last_seen.is_some_and(|m| m.confidence > own) {
+                e.perception.memory = self.last_seen;
+            }
+        }
+
+        let target = match self.last_seen {
+            Some(m) if members.len() > 1 => m.pos,
+            _ => {
+                // nothing to fight: stay together
+                for e in members.iter_mut() {
+                    e.role = if e.pos.distance(self.rally) > REGROUP_DISTANCE {
+                        Role::Regroup
+                    } else {
+                        Role::Free
+                    };
+                    set_key(e, "rally", self.rally);
+                }
+                return;
+            }
+        };
+
+        let n = members.len() as f64;
+        let center = members
+            .iter()
+            .fold(Vector::default(), |sum, e| sum + e.pos * (1.0 / n));
+
+        // bounding: one member covers the others, then another one takes over
+        if self.timer.is_multiple_of(BOUND_TIME) {
+            self.suppressor += 1;
+        }
+        self.suppressor %= members.len();
+        let refresh = self.timer.is_multiple_of(PATH_TIME);
+        let mut side = 1.0;
+        for (i, e) in members.iter_mut().enumerate() {
+            if i == self.suppressor {
+                e.role = Role::Suppress;
+                continue;
+            }
+            let flanking = e.role == Role::Flank;
+            e.role = Role::Flank;
+            if !flanking || refresh {
+                let path = nav.and_then(|nav| flank_path(nav, e.pos, target, center, side));
+                if let Some(brain) = e.brain.as_mut() {
+                    brain.path = path.unwrap_or_else(|| vec![target]);
+                }
+            }
+            set_key(e, "flank", target);
+            side = -side; // the next one goes around the other way
+        }
+    }
+}
+
+fn set_key(enemy: &mut Enemy, key: &str, pos: Vector<f64>) {
+    if let Some(brain) = enemy.brain.as_mut() {
+        brain.blackboard.set(key, pos);
+    }
+}
+
+/// Path to the target that goes around on one side (`side` is 1 or -1) of the line from the squad
+pub fn flank_path(
+    nav: &NavGrid,
+    from: Vector<f64>,
+    target: Vector<f64>,
+    center: Vector<f64>,
+    side: f64,
+) -> Option<Vec<Vector<f64>>> {
+    let line = target - center;
+    let len = line.length_squared().sqrt().max(1.0);
+    nav.find_path_with(from, target, |i| {
+        let p = nav.center(i % nav.width, i / nav.width) - center;
+        // signed distance to the line from the squad to the target
+        let d = (line.x * p.y - line.y * p.x) / len;
+        let near_target = (p - line).length_squared() < CORRIDOR * CORRIDOR;
+        if near_target || d * side > CORRIDOR {
+            0.0
+        } else {
+            3.0 * nav.cell
+        }
+    })
+}
+
+/// Puts the enemies without a squad in the closest one that has room, or in a new one
+pub fn assign_squads(squads: &mut Vec<Squad>, enemies: &mut [Enemy]) {
+    squads.retain(|s| enemies.iter().any(|e| e.squad == Some(s.id)));
+    for i in 0..enemies.len() {
+        if enemies[i].squad.is_some() {
+            continue;
+        }
+        let pos = enemies[i].pos;
+        let size = |id: usize| enemies.iter().filter(|e| e.squad == Some(id)).count();
+        let squad = squads
+            .iter()
+            .filter(|s| s.rally.distance(pos) < JOIN_DISTANCE && size(s.id) < SQUAD_SIZE)
+            .min_by(|a, b| a.rally.distance(pos).total_cmp(&b.rally.distance(pos)))
+            .map(|s| s.id);
+        let id = match squad {
+            Some(id) => id,
+            None => {
+                let id = squads.iter().map(|s| s.id + 1).max().unwrap_or(0);
+                squads.push(Squad::new(id, pos));
+                id
+            }
+        };
+        enemies[i].squad = Some(id);
+    }
+}
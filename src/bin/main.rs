@@ -2,65 +2,272 @@ extern crate find_folder;
 extern crate fps_counter;
 extern crate math_vector;
 extern crate piston_window;
+extern crate rand;
 extern crate serde;
 extern crate serde_json;
 
-use bullet_echo::{game::Game, HEIGHT, WIDTH};
+use bullet_echo::{
+    ai::Behaviours,
+    bot::Difficulty,
+    camera::CameraMode,
+    cli::{Command, Options, USAGE},
+    editor::Editor,
+    hero::Hero,
+    input::{Action, InputEvent, InputMap},
+    map::{generator, validate, Map},
+    replay::Replay,
+    state::{build_game, waves_from_options, App},
+    wave::Waves,
+    weapon::Catalogue,
+};
 use fps_counter::FPSCounter;
 use piston_window::*;
 
 fn main() {
-    let mut fps_counter = FPSCounter::new();
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let opts = Options::parse(&args).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        std::process::exit(2);
+    });
+
+    match opts.command.clone() {
+        Command::Play => play(&opts),
+        Command::Replay(path) => replay(&opts, &path),
+        Command::ValidateMap(path) => validate_map(&path),
+        Command::Benchmark(frames) => benchmark(&opts, frames),
+        Command::Edit(path) => edit(&opts, &path),
+        Command::Generate(layout, path) => generate(&opts, &layout, &path),
+        Command::Help => println!("{}", USAGE),
+    }
+}
+
+/// Main window and font used to render text
+fn build_window(title: &str, opts: &Options) -> (PistonWindow, Glyphs) {
     let assets = find_folder::Search::ParentsThenKids(3, 3)
         .for_folder("assets")
         .unwrap_or_else(|_| panic!("assets folder not found"));
 
-    let mut game = Game::new();
-    game.load_map("data/smolmap.json"); // put path to map here
-    game.spawn_enemy();
-
-    // main window
-    let mut window: PistonWindow = WindowSettings::new("Bullet Echo", [WIDTH, HEIGHT])
-        .exit_on_esc(true)
+    let mut window: PistonWindow = WindowSettings::new(title, [opts.width, opts.height])
+        .exit_on_esc(false)
+        .fullscreen(opts.fullscreen)
         .srgb(true)
         .build()
         .unwrap_or_else(|e| panic!("Failed to build PistonWindow: {}", e));
 
-    let mut glyphs = window
+    let glyphs = window
         .load_font(assets.join("RobotoMono-Thin.ttf"))
         .unwrap_or_else(|e| panic!("Failed to load font: {}", e));
 
-    // main loop
     window.set_ups(60);
-    window.set_max_fps(60);
+    window.set_max_fps(opts.fps);
     window.set_position([100, 10]);
+    (window, glyphs)
+}
+
+fn play(opts: &Options) {
+    let mut app = App::new(opts.clone());
+    let (mut window, mut glyphs) = build_window("Bullet Echo", opts);
+    let mut fps_counter = FPSCounter::new();
+
     while let Some(event) = window.next() {
-        if let Some(Button::Keyboard(key)) = event.press_args() {
-            game.add_key_pressed(key);
-        } else if let Some(Button::Keyboard(key)) = event.release_args() {
-            game.remove_key_pressed(key);
+        if let Some(input) = InputEvent::from_event(&event) {
+            app.handle_input(input);
+        }
+        if event.update_args().is_some() {
+            app.update(); // big stuff happening here
+        }
+        if app.quit {
+            window.set_should_close(true);
         }
-
-        game.update(); // big stuff happening here
 
         window.draw_2d(&event, |c, g, device| {
-            let fps = fps_counter.tick();
-            let transform = c.transform.trans(10., 20.); // transform for text
-
             clear([0.1; 4], g); // background
-            game.draw(&c, g, &mut glyphs); // draw game state
-
-            // render fps on window as text
-            text::Text::new_color([1.; 4], 9)
-                .draw(
-                    &mut format!("fps : {}", fps),
-                    &mut glyphs,
-                    &c.draw_state,
-                    transform,
-                    g,
-                )
-                .unwrap_or_else(|e| panic!("Failed to draw text: {}", e));
+            app.draw(&c, g, &mut glyphs); // draw the current screen
+            draw_fps(&c, g, &mut glyphs, fps_counter.tick());
             glyphs.factory.encoder.flush(device); // update glyphs before rendering
         });
     }
+    app.save_record();
+    // the window is closed, the replay that could not be written is reported here
+    if let Some(e) = app.error.as_ref() {
+        eprintln!("{}", e);
+    }
+}
+
+/// Play a recorded game again, the inputs of the window are ignored
+fn replay(opts: &Options, path: &str) {
+    let replay = Replay::load(path).unwrap_or_else(|e| panic!("Could not load replay: {}", e));
+    let hero =
+        Hero::from_name(&replay.hero).unwrap_or_else(|| panic!("Unknown hero {}", replay.hero));
+    let waves = Waves::from_value(&replay.waves)
+        .unwrap_or_else(|e| panic!("Could not load the waves of the replay: {}", e));
+    // the same bots as in the recorded game
+    let mut opts = opts.clone();
+    opts.allies = replay.allies;
+    opts.opponents = replay.opponents;
+    opts.difficulty = Difficulty::from_name(&replay.difficulty)
+        .unwrap_or_else(|| panic!("Unknown difficulty {}", replay.difficulty));
+    opts.camera = CameraMode::from_name(&replay.camera)
+        .unwrap_or_else(|| panic!("Unknown camera {}", replay.camera));
+    let opts = &opts;
+    let mut game = build_game(opts, &replay.map, &hero, replay.seed, waves)
+        .unwrap_or_else(|e| panic!("Could not set up the replay: {}", e));
+    game.screen = replay.screen;
+    game.input = InputMap::from_value(&replay.input)
+        .unwrap_or_else(|e| panic!("Could not load key bindings: {}", e));
+    // the weapons as they were (older replays do not have them), not reloaded when the file changes
+    if !replay.weapons.is_null() {
+        game.weapons = Catalogue::from_value(&replay.weapons)
+            .unwrap_or_else(|e| panic!("Could not load the weapons of the replay: {}", e));
+        game.rearm()
+            .unwrap_or_else(|e| panic!("Could not load the weapons of the replay: {}", e));
+    }
+    if !replay.ai.is_null() {
+        let behaviours = Behaviours::from_value(&replay.ai)
+            .unwrap_or_else(|e| panic!("Could not load the behaviour trees of the replay: {}", e));
+        game.set_behaviours(behaviours);
+    }
+
+    let (mut window, mut glyphs) = build_window("Bullet Echo - replay", opts);
+    let mut fps_counter = FPSCounter::new();
+
+    while let Some(event) = window.next() {
+        if event.update_args().is_some() {
+            // inputs are applied right before the update of the frame they were recorded at
+            for &input in replay.events_at(game.frame) {
+                game.handle_input(input);
+            }
+            game.update();
+        }
+        if let Some(Button::Keyboard(Key::Escape)) = event.press_args() {
+            window.set_should_close(true);
+        }
+
+        window.draw_2d(&event, |c, g, device| {
+            clear([0.1; 4], g);
+            game.draw(&c, g, &mut glyphs);
+            draw_fps(&c, g, &mut glyphs, fps_counter.tick());
+            glyphs.factory.encoder.flush(device);
+        });
+    }
+}
+
+/// Render fps on window as text
+fn draw_fps(c: &Context, g: &mut G2d, glyphs: &mut Glyphs, fps: usize) {
+    let transform = c.transform.trans(10., 20.); // transform for text
+    text::Text::new_color([1.; 4], 9)
+        .draw(
+            &format!("fps : {}", fps),
+            glyphs,
+            &c.draw_state,
+            transform,
+            g,
+        )
+        .unwrap_or_else(|e| panic!("Failed to draw text: {}", e));
+}
+
+/// Run the game without a window, the player keeps moving and shooting
+fn benchmark(opts: &Options, frames: u64) {
+    let hero = Hero::from_name(&opts.hero).unwrap_or_else(|| panic!("Unknown hero {}", opts.hero));
+    let waves =
+        waves_from_options(opts).unwrap_or_else(|e| panic!("Could not set up the game: {}", e));
+    let mut game = build_game(opts, &opts.map, &hero, opts.seed.unwrap_or(0), waves)
+        .unwrap_or_else(|e| panic!("Could not set up the game: {}", e));
+    for action in [Action::MoveForward, Action::StrafeRight, Action::Fire] {
+        let button = game
+            .input
+            .bindings_of(action)
+            .iter()
+            .find_map(|b| b.button())
+            .unwrap_or_else(|| panic!("No button bound to {}", action.name()));
+        game.press(button);
+    }
+
+    let start = std::time::Instant::now();
+    for _ in 0..frames {
+        game.update();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    println!(
+        "{} frames in {:.3}s: {:.3}ms per frame, {:.0} updates per second",
+        frames,
+        elapsed,
+        1000.0 * elapsed / frames.max(1) as f64,
+        frames as f64 / elapsed.max(f64::EPSILON)
+    );
+}
+
+/// Map editor: the map is created if the file does not exist yet
+fn edit(opts: &Options, path: &str) {
+    let map = match std::fs::read_to_string(path) {
+        Ok(data) => Map::from_value(
+            &serde_json::from_str(&data)
+                .unwrap_or_else(|_| panic!("Could not parse file {}", path)),
+        )
+        .unwrap_or_else(|e| panic!("Could not load map {}: {}", path, e)),
+        Err(_) => Map::new(),
+    };
+    let mut editor = Editor::new(map, path);
+    editor.screen = [opts.width as f64, opts.height as f64];
+
+    let (mut window, mut glyphs) = build_window("Bullet Echo - editor", opts);
+
+    while let Some(event) = window.next() {
+        if let Some(pos) = event.mouse_cursor_args() {
+            editor.move_cursor(pos);
+        }
+        if let Some(Button::Keyboard(Key::Escape)) = event.press_args() {
+            window.set_should_close(true);
+        }
+        if let Some(button) = event.press_args() {
+            editor.press(button);
+        } else if let Some(button) = event.release_args() {
+            editor.release(button);
+        }
+
+        editor.update();
+
+        window.draw_2d(&event, |c, g, device| {
+            clear([0.1; 4], g);
+            editor.draw(&c, g, &mut glyphs);
+            glyphs.factory.encoder.flush(device);
+        });
+    }
+}
+
+/// Generate a map: `generate <bsp|cave|arena> [output] --seed <n>`
+fn generate(opts: &Options, layout: &str, path: &str) {
+    let params = generator::GeneratorParams {
+        layout: generator::Layout::from_name(layout)
+            .unwrap_or_else(|| panic!("Unknown layout {} (bsp, cave or arena)", layout)),
+        seed: opts.seed.unwrap_or(0),
+        ..Default::default()
+    };
+
+    let map = generator::generate(&params)
+        .unwrap_or_else(|e| panic!("Could not generate a {} map: {}", layout, e));
+    map.save(path)
+        .unwrap_or_else(|e| panic!("Could not save {}: {}", path, e));
+    println!(
+        "{} walls and {} spawns written to {}",
+        map.walls.len(),
+        map.spawns.len(),
+        path
+    );
+}
+
+/// Print the issues found in a map, exits with an error code if there are any
+fn validate_map(path: &str) {
+    let map = Map::load(path).unwrap_or_else(|e| {
+        println!("{}", e);
+        std::process::exit(1);
+    });
+    let issues = validate::validate(&map);
+    for issue in issues.iter() {
+        println!("{}", issue);
+    }
+    println!("{}: {} issue(s)", path, issues.len());
+    if !issues.is_empty() {
+        std::process::exit(1);
+    }
 }
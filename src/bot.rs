@@ -0,0 +1,446 @@
+use math_vector::Vector;
+use piston_window::*;
+use rand::Rng;
+use ray_tracing::segments_intersect;
+
+use crate::{
+    ai::{cover::CoverMap, Senses},
+    input::Action,
+    map::{element::ElementKind, Map},
+    nav::NavGrid,
+    player::Player,
+    projectile::{Owner, Target},
+    PI,
+};
+
+/// Distance a target can move in a frame and still be the one seen the frame before
+const SAME_TARGET: f64 = 10.0;
+
+/// Frames between two draws of the aim error
+const AIM_CHANGE: u64 = 30;
+
+/// How well the bots play
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+/// What a bot is busy with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Goal {
+    Wander(Vector<f64>),      // walk to some place of the map
+    Investigate(Vector<f64>), // where an enemy was last seen or heard
+    Fight,                    // shoot at the target
+    Cover(Vector<f64>),       // hide from the target (to reload or when hurt)
+    Fetch(Vector<f64>),       // pick up an item
+}
+
+/// Computer controlled combatant, driving a `Player` with the same actions as a human
+pub struct Bot {
+    pub name: String,                   // shown when it is down
+    pub player: Player,                 // body, weapon and sight
+    pub side: Owner,                    // side it fights for
+    pub difficulty: Difficulty,         // aim, reactions and turn rate
+    pub goal: Goal,                     // what it is busy with
+    pub target: Option<usize>,          // fought combatant (index in `targets`)
+    pub last_seen: Option<Vector<f64>>, // where the last target was seen
+    pub heard: Option<Vector<f64>>,     // last sound worth checking
+    pub spotted: u64,                   // frames the target has been in sight
+    pub aim: Option<Vector<f64>>,       // point the bot turns to
+    pub aim_error: f64,                 // current aim offset (radians)
+    pub path: Vec<Vector<f64>>,         // waypoints to the goal
+    pub hearing: f64,                   // quietest sound the bot reacts to
+    pub hit_by_player: bool,            // the player dealt the last damage
+    strafe: f64,                        // strafing direction (-1 or 1)
+    timer: u64,                         // frames since the bot was created
+}
+
+impl Difficulty {
+    pub fn from_name(name: &str) -> Option<Difficulty> {
+        match name {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    /// Frames between spotting a target and shooting at it
+    pub fn reaction_time(&self) -> u64 {
+        match self {
+            Difficulty::Easy => 45,
+            Difficulty::Normal => 25,
+            Difficulty::Hard => 10,
+        }
+    }
+
+    /// Largest aim error (radians)
+    pub fn aim_error(&self) -> f64 {
+        match self {
+            Difficulty::Easy => PI / 16.0,
+            Difficulty::Normal => PI / 32.0,
+            Difficulty::Hard => PI / 90.0,
+        }
+    }
+
+    /// Fastest turn (radians per frame)
+    pub fn max_turn(&self) -> f64 {
+        match self {
+            Difficulty::Easy => PI / 90.0,
+            Difficulty::Normal => PI / 60.0,
+            Difficulty::Hard => PI / 40.0,
+        }
+    }
+}
+
+impl Bot {
+    pub fn new(name: &str, side: Owner, difficulty: Difficulty, pos: Vector<f64>) -> Bot {
+        let mut player = Player::new();
+        player.pos = pos;
+        Bot {
+            name: name.to_string(),
+            player,
+            side,
+            difficulty,
+            goal: Goal::Wander(pos),
+            target: None,
+            last_seen: None,
+            heard: None,
+            spotted: 0,
+            aim: None,
+            aim_error: 0.0,
+            path: Vec::new(),
+            hearing: 0.1,
+            hit_by_player: false,
+            strafe: 1.0,
+            timer: 0,
+        }
+    }
+
+    /// Moves the bot somewhere else and forgets what it knew (new map)
+    pub fn reset(&mut self, pos: Vector<f64>) {
+        self.player.pos = pos;
+        self.goal = Goal::Wander(pos);
+        self.target = None;
+        self.last_seen = None;
+        self.heard = None;
+        self.spotted = 0;
+        self.path.clear();
+    }
+
+    /// Decides what to do this frame from what the bot perceives, returns the actions to apply
+    pub fn think(&mut self, senses: &Senses, rng: &mut impl Rng) -> Vec<(Action, f64)> {
+        if self.timer.is_multiple_of(AIM_CHANGE) {
+            let e = self.difficulty.aim_error();
+            self.aim_error = rng.gen_range(-e..=e);
+        }
+        self.timer += 1;
+        self.perceive(senses);
+        self.choose_goal(senses, rng);
+        self.act(senses)
+    }
+
+    /// Enemies in the sight cone (the closest and weakest is the target) and sounds heard
+    fn perceive(&mut self, senses: &Senses) {
+        let (targets, sounds, map) = (senses.targets, senses.sounds, senses.map);
+        let p = &self.player;
+        let score = |t: &Target| p.pos.distance(t.pos) * (0.5 + t.health);
+        let target = targets
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.side != self.side && p.sees(t.pos, t.radius))
+            .min_by(|(_, a), (_, b)| score(a).total_cmp(&score(b)))
+            .map(|(i, _)| i);
+
+        match target {
+            Some(i) => {
+                // the indices change when someone dies: the same target is close to where it was
+                let same = self.target.is_some()
+                    && self
+                        .last_seen
+                        .is_some_and(|pos| pos.distance(targets[i].pos) < SAME_TARGET);
+                if !same {
+                    self.spotted = 0;
+                }
+                self.spotted += 1;
+                self.last_seen = Some(targets[i].pos);
+            }
+            None => self.spotted = 0,
+        }
+        self.target = target;
+
+        for s in sounds {
+            // its own shots do not count
+            if s.emitter != Some(senses.own) && s.volume_at(map, p.pos) >= self.hearing {
+                self.heard = Some(s.pos);
+            }
+        }
+    }
+
+    fn choose_goal(&mut self, senses: &Senses, rng: &mut impl Rng) {
+        let (targets, map) = (senses.targets, senses.map);
+        let p = &self.player;
+        let hurt = p.health < 0.35 * p.max_health;
+        let reloading = p.reload_cooldown > 0 || p.in_mag == 0;
+
+        self.goal = match self.target {
+            Some(i) if hurt || reloading => {
+                let threat = targets[i].pos;
+                match self.goal {
+                    Goal::Cover(c) if is_hidden(c, threat, map) => Goal::Cover(c),
+                    _ => self
+                        .find_cover(threat, map, senses.nav, senses.cover)
+                        .map_or(Goal::Fight, Goal::Cover),
+                }
+            }
+            Some(_) => Goal::Fight,
+            None => match (self.wanted_item(map), self.last_seen.or(self.heard)) {
+                (Some(item), _) => Goal::Fetch(item),
+                (None, Some(pos)) => Goal::Investigate(pos),
+                (None, None) => match self.goal {
+                    Goal::Wander(pos) if pos.distance(p.pos) > 20.0 => Goal::Wander(pos),
+                    _ => Goal::Wander(wander_point(map, p.pos, rng)),
+                },
+            },
+        };
+    }
+
+    fn act(&mut self, senses: &Senses) -> Vec<(Action, f64)> {
+        let targets = senses.targets;
+        let mut actions = Vec::new();
+        match self.goal {
+            Goal::Fight => {
+                let (pos, target) = (self.player.pos, targets[self.target.unwrap_or(0)].pos);
+                let aim = pos + (target - pos).rotate_z(self.aim_error);
+                self.aim = Some(aim);
+                if self.spotted >= self.difficulty.reaction_time() && self.is_facing(aim) {
+                    actions.push((Action::Fire, 1.0));
+                }
+
+                // dodge from side to side, and keep a good distance
+                if self.timer.is_multiple_of(90) {
+                    self.strafe = -self.strafe;
+                }
+                let right = self.player.heading.rotate_z(PI / 2.0).normalise();
+                let mut to = pos + right * self.strafe;
+                let d = pos.distance(target);
+                if d < 80.0 {
+                    to += (pos - target) * (1.0 / d.max(1.0));
+                } else if d > self.player.range * 0.8 {
+                    to += (target - pos) * (1.0 / d);
+                }
+                actions.extend(self.steer(to, 0.7));
+            }
+            Goal::Cover(pos) => {
+                // keep an eye on where the threat was
+                self.aim = self.last_seen;
+                let next = self.next_waypoint(pos, senses);
+                actions.extend(self.steer(next, 1.0));
+            }
+            Goal::Fetch(pos) | Goal::Investigate(pos) | Goal::Wander(pos) => {
+                let next = self.next_waypoint(pos, senses);
+                self.aim = Some(next);
+                actions.extend(self.steer(next, 1.0));
+                if pos.distance(self.player.pos) < 20.0 && matches!(self.goal, Goal::Investigate(_))
+                {
+                    self.last_seen = None;
+                    self.heard = None;
+                }
+            }
+        }
+
+        // reload when empty, or when it is quiet
+        let p = &self.player;
+        if p.in_mag == 0 || (self.target.is_none() && p.in_mag < p.mag_size / 2) {
+            actions.push((Action::Reload, 1.0));
+        }
+        actions
+    }
+
+    /// Whether the bot faces a point closely enough to shoot at it
+    fn is_facing(&self, point: Vector<f64>) -> bool {
+        let to = (point - self.player.pos).normalise();
+        let h = self.player.heading.normalise();
+        let angle = f64::acos((to.x * h.x + to.y * h.y).clamp(-1.0, 1.0));
+        angle <= f64::max(self.player.accuracy, PI / 60.0)
+    }
+
+    /// Movement actions to go towards a point, whatever the bot is facing
+    fn steer(&self, point: Vector<f64>, speed: f64) -> Vec<(Action, f64)> {
+        let to = point - self.player.pos;
+        if to.length_squared() < 1.0 {
+            return Vec::new();
+        }
+        let to = to.normalise();
+        let h = self.player.heading.normalise();
+        let right = h.rotate_z(PI / 2.0);
+        let forward = (to.x * h.x + to.y * h.y) * speed;
+        let side = (to.x * right.x + to.y * right.y) * speed;
+
+        let mut actions = Vec::new();
+        if forward > 0.05 {
+            actions.push((Action::MoveForward, forward));
+        } else if forward < -0.05 {
+            actions.push((Action::MoveBackward, -forward));
+        }
+        if side > 0.05 {
+            actions.push((Action::StrafeRight, side));
+        } else if side < -0.05 {
+            actions.push((Action::StrafeLeft, -side));
+        }
+        actions
+    }
+
+    /// Next point on the way to `dest` avoiding the danger (the path is recomputed from time to time)
+    fn next_waypoint(&mut self, dest: Vector<f64>, senses: &Senses) -> Vector<f64> {
+        let pos = self.player.pos;
+        let stale = self
+            .path
+            .last()
+            .is_none_or(|last| last.distance(dest) > 1.0);
+        if stale || self.timer.is_multiple_of(60) {
+            self.path = senses.find_path(pos, dest).unwrap_or_else(|| vec![dest]);
+        }
+        while self.path.len() > 1 && self.path[0].distance(pos) < self.player.radius {
+            self.path.remove(0);
+        }
+        self.path.first().copied().unwrap_or(dest)
+    }
+
+    /// Free spot close to the bot that cannot be seen from `threat` (a cover point of the map if any)
+    fn find_cover(
+        &self,
+        threat: Vector<f64>,
+        map: &Map,
+        nav: Option<&NavGrid>,
+        cover: Option<&CoverMap>,
+    ) -> Option<Vector<f64>> {
+        let pos = self.player.pos;
+        let walkable = |c: Vector<f64>| nav.is_none_or(|n| n.is_walkable(c));
+        let point = cover.and_then(|cover| {
+            cover
+                .points
+                .iter()
+                .map(|p| p.pos)
+                .filter(|&c| c.distance(pos) <= 160.0 && walkable(c) && is_hidden(c, threat, map))
+                .min_by(|a, b| a.distance(pos).total_cmp(&b.distance(pos)))
+        });
+        if point.is_some() {
+            return point;
+        }
+        for ring in 1..=4 {
+            let r = 40.0 * ring as f64;
+            for k in 0..16 {
+                let a = k as f64 * PI / 8.0;
+                let c = pos + Vector::new(r * a.cos(), r * a.sin(), 0.0);
+                if walkable(c) && map.is_free(c, self.player.radius) && is_hidden(c, threat, map) {
+                    return Some(c);
+                }
+            }
+        }
+        None
+    }
+
+    /// Closest item the bot needs (health when hurt, ammo when running low, ...)
+    fn wanted_item(&self, map: &Map) -> Option<Vector<f64>> {
+        let p = &self.player;
+        map.elements
+            .iter()
+            .filter(|e| e.is_active() && e.pos.distance(p.pos) < 500.0)
+            .filter(|e| match &e.kind {
+                ElementKind::Health(_) => p.health < 0.6 * p.max_health,
+                ElementKind::Armor(_) => p.armor < 0.5 * p.max_armor,
+                ElementKind::Ammo(_) => p.ammo < p.mag_size,
+                ElementKind::Weapon { name, .. } => !p.weapons.contains(name),
+                ElementKind::Trigger(_) => false,
+            })
+            .map(|e| e.pos)
+            .min_by(|a, b| a.distance(p.pos).total_cmp(&b.distance(p.pos)))
+    }
+}
+
+impl Bot {
+    pub fn draw(&self, _c: &Context, g: &mut G2d, transform: math::Matrix2d) {
+        let p = &self.player;
+        let col = match self.side {
+            Owner::Player => [0.2, 0.6, 1.0, 1.0],
+            Owner::Enemy => [1.0, 0.4, 0.1, 1.0],
+        };
+        let r = p.radius;
+        ellipse(
+            col,
+            [p.pos.x - r, p.pos.y - r, r * 2.0, r * 2.0],
+            transform,
+            g,
+        );
+        let tip = p.pos + p.heading.normalise() * (r * 1.5);
+        line(col, 1.0, [p.pos.x, p.pos.y, tip.x, tip.y], transform, g);
+
+        let health = p.health / p.max_health;
+        let (x, y) = (p.pos.x - 15.0, p.pos.y - r - 8.0);
+        rectangle([0.2, 0.2, 0.2, 0.8], [x, y, 30.0, 3.0], transform, g);
+        rectangle(
+            [0.8, 0.1, 0.1, 0.9],
+            [x, y, 30.0 * health, 3.0],
+            transform,
+            g,
+        );
+    }
+
+    /// Path and aim of the bot (debug overlay)
+    pub fn draw_debug(&self, _c: &Context, g: &mut G2d, transform: math::Matrix2d) {
+        let mut from = self.player.pos;
+        for &p in self.path.iter() {
+            line(
+                [0.9, 0.9, 0.2, 0.6],
+                0.5,
+                [from.x, from.y, p.x, p.y],
+                transform,
+                g,
+            );
+            from = p;
+        }
+        if let Some(aim) = self.aim {
+            let p = self.player.pos;
+            line(
+                [1.0, 0.2, 0.2, 0.4],
+                0.5,
+                [p.x, p.y, aim.x, aim.y],
+                transform,
+                g,
+            );
+        }
+    }
+}
+
+/// Whether walls that block the sight are between two points
+pub fn is_hidden(from: Vector<f64>, to: Vector<f64>, map: &Map) -> bool {
+    map.walls
+        .iter()
+        .any(|w| w.blocks_sight() && segments_intersect(from, to, w.p1, w.p2).is_some())
+}
+
+/// Somewhere to go: a spawn point or an item of the map, or a random nearby point
+pub fn wander_point(map: &Map, pos: Vector<f64>, rng: &mut impl Rng) -> Vector<f64> {
+    let places = map
+        .spawns
+        .iter()
+        .map(|s| s.pos)
+        .chain(map.elements.iter().map(|e| e.pos))
+        .collect::<Vec<Vector<f64>>>();
+    if places.is_empty() {
+        let a = rng.gen_range(0.0..2.0 * PI);
+        return pos + Vector::new(a.cos(), a.sin(), 0.0) * 150.0;
+    }
+    places[rng.gen_range(0..places.len())]
+}
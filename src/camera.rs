@@ -0,0 +1,160 @@
+use math_vector::Vector;
+use piston_window::*;
+
+use crate::{player::Player, HEIGHT, PI, WIDTH};
+
+/// Fraction of the way to the followed point covered each frame
+const FOLLOW: f64 = 0.25;
+/// Fraction of the rotation to the heading covered each frame
+const TURN: f64 = 0.4;
+/// Fraction of the change of zoom done each frame
+const ZOOM_SPEED: f64 = 0.05;
+/// Part of half the screen (its smaller side) taken by the field of view of the player
+const FOV_SCREEN: f64 = 2.0 / 3.0;
+/// Strongest shake (pixels)
+const MAX_SHAKE: f64 = 12.0;
+/// Part of the shake left after a frame
+const SHAKE_FADE: f64 = 0.85;
+/// Speed of the spectator camera (pixels per frame)
+const FREE_SPEED: f64 = 8.0;
+
+/// How the camera follows the player
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraMode {
+    HeadingUp, // centered on the player, who always faces up
+    NorthUp,   // centered on the player, the map does not turn
+    Free,      // spectator: the movement keys move the camera and the player stands still
+}
+
+/// Point of view of the game: what `Game::draw` shows and where the mouse points to
+pub struct Camera {
+    pub mode: CameraMode,
+    pub pos: Vector<f64>, // world point at the center of the screen
+    pub angle: f64,       // rotation of the world on screen (radians)
+    pub zoom: f64,        // screen pixels per world unit
+    pub screen: [f64; 2], // size of the window
+    pub smooth: bool,     // false to stick to the player
+    shake: f64,           // strength of the shake (pixels), fades out
+    frame: u64,           // frames since the camera was created (shake pattern)
+    cut: bool,            // jump to the player on the next update
+}
+
+impl CameraMode {
+    pub fn from_name(name: &str) -> Option<CameraMode> {
+        match name {
+            "heading-up" => Some(CameraMode::HeadingUp),
+            "north-up" => Some(CameraMode::NorthUp),
+            "free" => Some(CameraMode::Free),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            CameraMode::HeadingUp => "heading-up",
+            CameraMode::NorthUp => "north-up",
+            CameraMode::Free => "free",
+        }
+    }
+
+    pub fn next(&self) -> CameraMode {
+        match self {
+            CameraMode::HeadingUp => CameraMode::NorthUp,
+            CameraMode::NorthUp => CameraMode::Free,
+            CameraMode::Free => CameraMode::HeadingUp,
+        }
+    }
+}
+
+impl Camera {
+    pub fn new() -> Camera {
+        Camera {
+            mode: CameraMode::HeadingUp,
+            pos: Vector::default(),
+            angle: 0.0,
+            zoom: 1.0,
+            screen: [WIDTH as f64, HEIGHT as f64],
+            smooth: true,
+            shake: 0.0,
+            frame: 0,
+            cut: true,
+        }
+    }
+
+    /// Moves towards the player for one frame, `pan` moves the spectator camera (screen directions)
+    pub fn update(&mut self, player: &Player, screen: [f64; 2], pan: Vector<f64>) {
+        self.frame += 1;
+        self.screen = screen;
+        self.shake *= SHAKE_FADE;
+        let snap = self.cut || !self.smooth;
+        self.cut = false;
+        let rate = |r: f64| if snap { 1.0 } else { r };
+
+        // the field of view of the player takes the same part of the screen whatever the hero
+        let zoom = f64::min(screen[0], screen[1]) / 2.0 * FOV_SCREEN / player.fov_radius.max(1.0);
+        self.zoom += (zoom - self.zoom) * rate(ZOOM_SPEED);
+
+        let angle = match self.mode {
+            CameraMode::HeadingUp => -player.heading.angle() - PI / 2.0,
+            CameraMode::NorthUp => 0.0,
+            CameraMode::Free => self.angle,
+        };
+        // the shortest way round
+        let turn = (angle - self.angle + PI).rem_euclid(2.0 * PI) - PI;
+        self.angle += turn * rate(TURN);
+
+        if self.mode == CameraMode::Free {
+            self.pos += pan.rotate_z(-self.angle) * (FREE_SPEED / self.zoom);
+        } else {
+            self.pos += (player.pos - self.pos) * rate(FOLLOW);
+        }
+    }
+
+    /// Jumps to the player on the next update instead of moving there smoothly (new map, new mode)
+    pub fn cut(&mut self) {
+        self.cut = true;
+    }
+
+    /// Shakes the view, more for bigger hits
+    pub fn shake(&mut self, amount: f64) {
+        self.shake = f64::min(self.shake + amount * 0.5, MAX_SHAKE);
+    }
+
+    pub fn cycle_mode(&mut self) {
+        self.mode = self.mode.next();
+        if self.mode != CameraMode::Free {
+            self.cut();
+        }
+    }
+
+    /// Offset of the view this frame because of the shake
+    fn offset(&self) -> Vector<f64> {
+        let t = self.frame as f64;
+        Vector::new((t * 1.7).sin(), (t * 2.3).cos(), 0.0) * self.shake
+    }
+
+    /// Transform from world coordinates to the screen
+    pub fn transform(&self, base: math::Matrix2d) -> math::Matrix2d {
+        let s = self.offset();
+        base.trans(self.screen[0] / 2.0 + s.x, self.screen[1] / 2.0 + s.y)
+            .zoom(self.zoom)
+            .rot_rad(self.angle)
+            .trans(-self.pos.x, -self.pos.y)
+    }
+
+    /// Position on the screen of a world point
+    pub fn world_to_screen(&self, pos: Vector<f64>) -> [f64; 2] {
+        let s = (pos - self.pos).rotate_z(self.angle) * self.zoom + self.offset();
+        [s.x + self.screen[0] / 2.0, s.y + self.screen[1] / 2.0]
+    }
+
+    /// World point under a position of the screen
+    pub fn screen_to_world(&self, pos: [f64; 2]) -> Vector<f64> {
+        let s = Vector::new(
+            pos[0] - self.screen[0] / 2.0,
+            pos[1] - self.screen[1] / 2.0,
+            0.0,
+        ) - self.offset();
+        s.rotate_z(-self.angle) * (1.0 / self.zoom) + self.pos
+    }
+}
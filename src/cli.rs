@@ -0,0 +1,221 @@
+use crate::{bot::Difficulty, camera::CameraMode, enemy::EnemyKind, hero::Hero, HEIGHT, WIDTH};
+
+pub const USAGE: &str = "usage: bullet-echo [command] [options]
+
+commands:
+    play                      play the game (default)
+    replay <file>             watch a recorded game
+    validate-map <file>       check a map for broken geometry
+    benchmark [frames]        run the game without window and time the updates
+    edit <file>               open the map editor
+    generate <layout> [file]  generate a map (bsp, cave or arena)
+
+options:
+    --map <file>              map to play on (default data/smolmap.json)
+    --hero <name>             hero: soldier, scout or tank (default soldier)
+    --no-menu                 start playing right away
+    --levels <file>           campaign: maps and waves of each level (endless waves otherwise)
+    --weapons <file>          weapon catalogue, reloaded when it changes (default data/weapons.json)
+    --ai <dir>                behaviour trees of the enemies, one file per kind (default data/ai)
+    --bots <n>                number of enemies of the first endless wave (default 1)
+    --bot-kind <kind>         main kind of enemies: grunt, scout or heavy (default grunt)
+    --allies <n>              number of bots playing with you (default 0)
+    --opponents <n>           number of bots playing against you (default 0)
+    --difficulty <level>      skill of the bots: easy, normal or hard (default normal)
+    --seed <n>                seed of the random number generator
+    --size <w>x<h>            window size (default 800x600)
+    --fullscreen              fullscreen window
+    --fps <n>                 frame rate cap (default 60)
+    --record <file>           record the game to a replay file
+    --input <preset|file>     key bindings: azerty, qwerty or a config file (default azerty)
+    --mouse-aim               turn towards the mouse cursor
+    --camera <mode>           heading-up, north-up or free (spectator) camera (default heading-up)
+    --debug <overlays>        comma separated debug overlays: qtree, spawns, bots, ai, cover, danger
+    -h, --help                print this message";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Play,
+    Replay(String),
+    ValidateMap(String),
+    Benchmark(u64),
+    Edit(String),
+    Generate(String, String),
+    Help,
+}
+
+/// Debug overlays drawn on top of the game
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DebugFlags {
+    pub qtree: bool,  // static and dynamic quadtrees
+    pub spawns: bool, // spawn points
+    pub bots: bool,   // paths and aim of the bots
+    pub ai: bool,     // active node of the behaviour tree of the enemies
+    pub cover: bool,  // cover points rated against the player
+    pub danger: bool, // danger map of the side of the player
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub command: Command,
+    pub map: String,
+    pub hero: String,
+    pub no_menu: bool,
+    pub levels: Option<String>,
+    pub weapons: String,
+    pub ai: String,
+    pub bots: usize,
+    pub bot_kind: EnemyKind,
+    pub allies: usize,
+    pub opponents: usize,
+    pub difficulty: Difficulty,
+    pub seed: Option<u64>,
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+    pub fps: u64,
+    pub record: Option<String>,
+    pub input: String,
+    pub mouse_aim: bool,
+    pub camera: CameraMode,
+    pub debug: DebugFlags,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            command: Command::Play,
+            map: "data/smolmap.json".to_string(),
+            hero: "soldier".to_string(),
+            no_menu: false,
+            levels: None,
+            weapons: "data/weapons.json".to_string(),
+            ai: "data/ai".to_string(),
+            bots: 1,
+            bot_kind: EnemyKind::Grunt,
+            allies: 0,
+            opponents: 0,
+            difficulty: Difficulty::Normal,
+            seed: None,
+            width: WIDTH,
+            height: HEIGHT,
+            fullscreen: false,
+            fps: 60,
+            record: None,
+            input: "azerty".to_string(),
+            mouse_aim: false,
+            camera: CameraMode::HeadingUp,
+            debug: DebugFlags::default(),
+        }
+    }
+}
+
+impl DebugFlags {
+    pub fn parse(list: &str) -> Result<DebugFlags, String> {
+        let mut flags = DebugFlags::default();
+        for name in list.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            match name {
+                "qtree" => flags.qtree = true,
+                "spawns" => flags.spawns = true,
+                "bots" => flags.bots = true,
+                "ai" => flags.ai = true,
+                "cover" => flags.cover = true,
+                "danger" => flags.danger = true,
+                _ => return Err(format!("unknown debug overlay {}", name)),
+            }
+        }
+        Ok(flags)
+    }
+}
+
+impl Options {
+    /// Parse the command line arguments (without the name of the program)
+    pub fn parse(args: &[String]) -> Result<Options, String> {
+        let mut opts = Options::default();
+        let mut positional = Vec::new();
+        let mut it = args.iter();
+
+        while let Some(arg) = it.next() {
+            let mut value = |name: &str| {
+                it.next()
+                    .cloned()
+                    .ok_or_else(|| format!("missing value for {}", name))
+            };
+            match arg.as_str() {
+                "-h" | "--help" => opts.command = Command::Help,
+                "--map" => opts.map = value(arg)?,
+                "--hero" => {
+                    opts.hero = value(arg)?;
+                    if Hero::from_name(&opts.hero).is_none() {
+                        return Err(format!("unknown hero {}", opts.hero));
+                    }
+                }
+                "--no-menu" => opts.no_menu = true,
+                "--levels" => opts.levels = Some(value(arg)?),
+                "--weapons" => opts.weapons = value(arg)?,
+                "--ai" => opts.ai = value(arg)?,
+                "--bots" => opts.bots = parse_number(&value(arg)?, arg)?,
+                "--bot-kind" => {
+                    let kind = value(arg)?;
+                    opts.bot_kind = EnemyKind::from_name(&kind)
+                        .ok_or_else(|| format!("unknown bot kind {}", kind))?;
+                }
+                "--allies" => opts.allies = parse_number(&value(arg)?, arg)?,
+                "--opponents" => opts.opponents = parse_number(&value(arg)?, arg)?,
+                "--difficulty" => {
+                    let level = value(arg)?;
+                    opts.difficulty = Difficulty::from_name(&level)
+                        .ok_or_else(|| format!("unknown difficulty {}", level))?;
+                }
+                "--seed" => opts.seed = Some(parse_number(&value(arg)?, arg)?),
+                "--size" => {
+                    let size = value(arg)?;
+                    let (w, h) = size
+                        .split_once('x')
+                        .ok_or_else(|| format!("invalid size {} (expected <w>x<h>)", size))?;
+                    opts.width = parse_number(w, arg)?;
+                    opts.height = parse_number(h, arg)?;
+                }
+                "--fullscreen" => opts.fullscreen = true,
+                "--fps" => opts.fps = parse_number(&value(arg)?, arg)?,
+                "--record" => opts.record = Some(value(arg)?),
+                "--input" => opts.input = value(arg)?,
+                "--mouse-aim" => opts.mouse_aim = true,
+                "--camera" => {
+                    let mode = value(arg)?;
+                    opts.camera = CameraMode::from_name(&mode)
+                        .ok_or_else(|| format!("unknown camera {}", mode))?;
+                }
+                "--debug" => opts.debug = DebugFlags::parse(&value(arg)?)?,
+                a if a.starts_with('-') => return Err(format!("unknown option {}", a)),
+                a => positional.push(a.to_string()),
+            }
+        }
+
+        if opts.command == Command::Help {
+            return Ok(opts);
+        }
+        let arg = |i: usize| positional.get(i).cloned();
+        opts.command = match positional.first().map(|s| s.as_str()) {
+            None | Some("play") => Command::Play,
+            Some("replay") => Command::Replay(arg(1).ok_or("missing replay file")?),
+            Some("validate-map") => Command::ValidateMap(arg(1).unwrap_or(opts.map.clone())),
+            Some("benchmark") => match arg(1) {
+                Some(n) => Command::Benchmark(parse_number(&n, "benchmark")?),
+                None => Command::Benchmark(1000),
+            },
+            Some("edit") => Command::Edit(arg(1).unwrap_or(opts.map.clone())),
+            Some("generate") => Command::Generate(
+                arg(1).unwrap_or("bsp".to_string()),
+                arg(2).unwrap_or("data/generated.json".to_string()),
+            ),
+            Some(c) => return Err(format!("unknown command {}", c)),
+        };
+        Ok(opts)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str, name: &str) -> Result<T, String> {
+    s.parse()
+        .map_err(|_| format!("invalid value {} for {}", s, name))
+}
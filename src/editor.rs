@@ -0,0 +1,387 @@
+use math_vector::Vector;
+use piston_window::*;
+use serde_json::Value;
+
+use crate::{
+    map::{
+        element::{Element, ElementKind},
+        shape::{Obstacle, Shape},
+        spawn::{Spawn, SpawnKind},
+        wall::Wall,
+        Map,
+    },
+    HEIGHT, WIDTH,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tool {
+    Wall,             // click the two end points of a wall
+    Polygon,          // click the points of a polygon, enter to close it
+    Spawn(SpawnKind), // click to place a spawn point
+    Element(usize),   // click to place an element (index in `ELEMENTS`)
+}
+
+/// Elements that can be placed with the element tool
+const ELEMENTS: [(&str, f64); 3] = [("ammo", 30.0), ("health", 25.0), ("armor", 50.0)];
+
+pub struct Editor {
+    pub map: Map,     // the map being edited
+    pub path: String, // where the map is saved
+
+    pub tool: Tool,                      // current tool
+    pub grid: f64,                       // size of the grid cells
+    pub snap: bool,                      // whether points snap to the grid
+    pub offset: Vector<f64>,             // world position at the center of the screen
+    pub cursor: Vector<f64>,             // world position of the mouse
+    pub pending: Vec<Vector<f64>>,       // points of the wall / polygon being drawn
+    pub dragging: Option<(usize, bool)>, // wall and end point (true for p1) being dragged
+    pub key_pressed: Vec<Key>,           // all pressed keys
+    pub message: String,                 // feedback shown on screen
+    pub screen: [f64; 2],                // size of the window
+
+    undo: Vec<Value>, // previous states of the map
+    redo: Vec<Value>, // undone states of the map
+}
+
+impl Editor {
+    pub fn new(map: Map, path: &str) -> Editor {
+        Editor {
+            map,
+            path: path.to_string(),
+
+            tool: Tool::Wall,
+            grid: 20.0,
+            snap: true,
+            offset: Vector::default(),
+            cursor: Vector::default(),
+            pending: Vec::new(),
+            dragging: None,
+            key_pressed: Vec::new(),
+            message: String::new(),
+            screen: [WIDTH as f64, HEIGHT as f64],
+
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    /// Snap a point to the grid (if enabled)
+    fn snapped(&self, p: Vector<f64>) -> Vector<f64> {
+        if !self.snap {
+            return p;
+        }
+        Vector::new(
+            (p.x / self.grid).round() * self.grid,
+            (p.y / self.grid).round() * self.grid,
+            0.0,
+        )
+    }
+
+    /// Convert a position on the window to a position in the world
+    pub fn screen_to_world(&self, pos: [f64; 2]) -> Vector<f64> {
+        let (cx, cy) = (self.screen[0] / 2.0, self.screen[1] / 2.0);
+        Vector::new(
+            pos[0] - cx + self.offset.x,
+            pos[1] - cy + self.offset.y,
+            0.0,
+        )
+    }
+
+    /// Save the current state of the map so it can be undone
+    fn checkpoint(&mut self) {
+        self.undo.push(self.map.to_value());
+        self.redo.clear();
+    }
+
+    pub fn undo(&mut self) {
+        if let Some(v) = self.undo.pop() {
+            self.dragging = None; // the walls are replaced
+            match Map::from_value(&v) {
+                Ok(map) => {
+                    self.redo.push(self.map.to_value());
+                    self.map = map;
+                    self.message = "undo".to_string();
+                }
+                Err(e) => self.message = format!("could not undo: {}", e),
+            }
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(v) = self.redo.pop() {
+            self.dragging = None;
+            match Map::from_value(&v) {
+                Ok(map) => {
+                    self.undo.push(self.map.to_value());
+                    self.map = map;
+                    self.message = "redo".to_string();
+                }
+                Err(e) => self.message = format!("could not redo: {}", e),
+            }
+        }
+    }
+
+    pub fn save(&mut self) {
+        self.message = match self.map.save(&self.path) {
+            Ok(()) => format!("saved to {}", self.path),
+            Err(e) => format!("could not save {}: {}", self.path, e),
+        };
+    }
+
+    pub fn move_cursor(&mut self, pos: [f64; 2]) {
+        self.cursor = self.screen_to_world(pos);
+        if let Some((i, first)) = self.dragging {
+            let p = self.snapped(self.cursor);
+            if first {
+                self.map.walls[i].p1 = p;
+            } else {
+                self.map.walls[i].p2 = p;
+            }
+        }
+    }
+
+    /// Closest free wall end point under the cursor (walls of shapes cannot be dragged)
+    fn endpoint_at(&self, p: Vector<f64>) -> Option<(usize, bool)> {
+        let reach = 6.0;
+        self.map
+            .walls
+            .iter()
+            .enumerate()
+            .filter(|(_, w)| w.shape.is_none())
+            .flat_map(|(i, w)| [(i, true, w.p1.distance(p)), (i, false, w.p2.distance(p))])
+            .filter(|(_, _, d)| *d <= reach)
+            .min_by(|a, b| a.2.total_cmp(&b.2))
+            .map(|(i, first, _)| (i, first))
+    }
+
+    pub fn press(&mut self, button: Button) {
+        match button {
+            Button::Mouse(MouseButton::Left) => self.click(),
+            Button::Mouse(MouseButton::Right) => self.erase(),
+            Button::Keyboard(key) if !self.key_pressed.contains(&key) => {
+                self.key_pressed.push(key);
+                self.key(key);
+            }
+            _ => (),
+        }
+    }
+
+    pub fn release(&mut self, button: Button) {
+        match button {
+            Button::Mouse(MouseButton::Left) if self.dragging.take().is_some() => {
+                self.map.dirty = true;
+            }
+            Button::Keyboard(key) => self.key_pressed.retain(|&k| k != key),
+            _ => (),
+        }
+    }
+
+    fn key(&mut self, key: Key) {
+        let ctrl = self.key_pressed.contains(&Key::LCtrl) || self.key_pressed.contains(&Key::RCtrl);
+        match key {
+            Key::Z if ctrl => self.undo(),
+            Key::Y if ctrl => self.redo(),
+            Key::S if ctrl => self.save(),
+            Key::D1 => self.tool = Tool::Wall,
+            Key::D2 => self.tool = Tool::Polygon,
+            Key::D3 => self.tool = Tool::Spawn(SpawnKind::Player),
+            Key::D4 => self.tool = Tool::Spawn(SpawnKind::Enemy),
+            Key::D5 => self.tool = Tool::Element(0),
+            Key::Tab => {
+                if let Tool::Element(i) = self.tool {
+                    self.tool = Tool::Element((i + 1) % ELEMENTS.len());
+                }
+            }
+            Key::G => self.snap = !self.snap,
+            Key::RightBracket => self.grid = f64::min(self.grid * 2.0, 160.0),
+            Key::LeftBracket => self.grid = f64::max(self.grid / 2.0, 5.0),
+            Key::Return => self.close_polygon(),
+            Key::Backspace => self.pending.clear(),
+            _ => (),
+        }
+        if !matches!(self.tool, Tool::Wall | Tool::Polygon) {
+            self.pending.clear();
+        }
+    }
+
+    fn click(&mut self) {
+        if let Some(endpoint) = self.endpoint_at(self.cursor) {
+            if self.pending.is_empty() {
+                self.checkpoint();
+                self.dragging = Some(endpoint);
+                return;
+            }
+        }
+
+        let p = self.snapped(self.cursor);
+        match self.tool {
+            Tool::Wall => {
+                if let Some(&p1) = self.pending.first() {
+                    self.checkpoint();
+                    self.map.walls.push(Wall::new(p1, p));
+                    self.map.dirty = true;
+                    self.pending.clear();
+                } else {
+                    self.pending.push(p);
+                }
+            }
+            Tool::Polygon => self.pending.push(p),
+            Tool::Spawn(kind) => {
+                self.checkpoint();
+                self.map.spawns.push(Spawn::new(p, kind));
+            }
+            Tool::Element(i) => {
+                let (name, amount) = ELEMENTS[i];
+                match ElementKind::from_name(name, amount, None, None) {
+                    Some(kind) => {
+                        self.checkpoint();
+                        self.map.elements.push(Element::new(kind, p, 0));
+                    }
+                    None => self.message = format!("invalid element {}", name),
+                }
+            }
+        }
+    }
+
+    /// Turn the pending points into a closed polygon
+    fn close_polygon(&mut self) {
+        if self.tool != Tool::Polygon || self.pending.len() < 3 {
+            return;
+        }
+        self.checkpoint();
+        let shape = Shape::Polygon {
+            points: std::mem::take(&mut self.pending),
+            closed: true,
+        };
+        let template = Wall::new(Vector::default(), Vector::default());
+        self.map.add_obstacle(Obstacle::new(shape, template));
+    }
+
+    /// Remove whatever is under the cursor (spawn, element, wall or obstacle)
+    fn erase(&mut self) {
+        let p = self.cursor;
+        let reach = 6.0;
+
+        if let Some(i) = self
+            .map
+            .spawns
+            .iter()
+            .position(|s| s.pos.distance(p) <= reach)
+        {
+            self.checkpoint();
+            self.map.spawns.remove(i);
+        } else if let Some(i) = self
+            .map
+            .elements
+            .iter()
+            .position(|e| e.pos.distance(p) <= e.radius)
+        {
+            self.checkpoint();
+            self.map.elements.remove(i);
+        } else if let Some(i) = self
+            .map
+            .walls
+            .iter()
+            .position(|w| w.distance_to(p) <= reach)
+        {
+            self.checkpoint();
+            match self.map.walls[i].shape {
+                Some(id) => self.map.remove_obstacle(id),
+                None => {
+                    self.map.walls.remove(i);
+                    self.map.dirty = true;
+                }
+            }
+        }
+    }
+
+    pub fn update(&mut self) {
+        let speed = 4.0;
+        for key in self.key_pressed.iter() {
+            match key {
+                Key::Up => self.offset.y -= speed,
+                Key::Down => self.offset.y += speed,
+                Key::Left => self.offset.x -= speed,
+                Key::Right => self.offset.x += speed,
+                _ => (),
+            }
+        }
+        self.map.update();
+    }
+
+    fn draw_grid(&self, g: &mut G2d, transform: math::Matrix2d) {
+        let (w, h) = (self.screen[0] / 2.0, self.screen[1] / 2.0);
+        let color = [1.0, 1.0, 1.0, 0.05];
+        let x0 = ((self.offset.x - w) / self.grid).floor() as i64;
+        let x1 = ((self.offset.x + w) / self.grid).ceil() as i64;
+        let y0 = ((self.offset.y - h) / self.grid).floor() as i64;
+        let y1 = ((self.offset.y + h) / self.grid).ceil() as i64;
+        for i in x0..=x1 {
+            let x = i as f64 * self.grid;
+            let l = [x, self.offset.y - h, x, self.offset.y + h];
+            line(color, 0.5, l, transform, g);
+        }
+        for j in y0..=y1 {
+            let y = j as f64 * self.grid;
+            let l = [self.offset.x - w, y, self.offset.x + w, y];
+            line(color, 0.5, l, transform, g);
+        }
+    }
+
+    pub fn draw(&mut self, c: &Context, g: &mut G2d, glyphs: &mut Glyphs) {
+        let (cx, cy) = (self.screen[0] / 2.0, self.screen[1] / 2.0);
+        let transform = c.transform.trans(cx - self.offset.x, cy - self.offset.y);
+
+        self.draw_grid(g, transform);
+        self.map.draw(c, g, transform);
+        for spawn in self.map.spawns.iter() {
+            spawn.draw(c, g, transform);
+        }
+
+        // wall / polygon being drawn
+        let p = self.snapped(self.cursor);
+        let mut pts = self.pending.clone();
+        if !pts.is_empty() {
+            pts.push(p);
+        }
+        for w in pts.windows(2) {
+            line(
+                [1.0, 1.0, 0.0, 1.0],
+                1.0,
+                [w[0].x, w[0].y, w[1].x, w[1].y],
+                transform,
+                g,
+            );
+        }
+
+        // cursor
+        let color = [1.0, 1.0, 0.0, 1.0];
+        line(color, 1.0, [p.x - 4.0, p.y, p.x + 4.0, p.y], transform, g);
+        line(color, 1.0, [p.x, p.y - 4.0, p.x, p.y + 4.0], transform, g);
+
+        let tool = match self.tool {
+            Tool::Wall => "wall".to_string(),
+            Tool::Polygon => "polygon".to_string(),
+            Tool::Spawn(kind) => format!("{} spawn", kind.name()),
+            Tool::Element(i) => ELEMENTS[i].0.to_string(),
+        };
+        let status = format!(
+            "[1-5] tool : {}   [g] snap : {}   [ ] grid : {}   ({:.0}, {:.0})   {}",
+            tool,
+            if self.snap { "on" } else { "off" },
+            self.grid,
+            p.x,
+            p.y,
+            self.message
+        );
+        text::Text::new_color([1.; 4], 9)
+            .draw(
+                &status,
+                glyphs,
+                &c.draw_state,
+                c.transform.trans(10.0, self.screen[1] - 10.0),
+                g,
+            )
+            .unwrap_or_else(|e| panic!("Failed to draw text: {}", e));
+    }
+}
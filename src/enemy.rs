@@ -1,9 +1,147 @@
 use math_vector::Vector;
 use piston_window::*;
+use rand::Rng;
+
+use crate::{
+    ai::{perception::Perception, squad::Role, Brain, Senses},
+    map::Map,
+    PI,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EnemyKind {
+    Grunt, // average at everything
+    Scout, // fast but fragile
+    Heavy, // slow but tough
+}
+
+impl EnemyKind {
+    pub fn from_name(name: &str) -> Option<EnemyKind> {
+        match name {
+            "grunt" => Some(EnemyKind::Grunt),
+            "scout" => Some(EnemyKind::Scout),
+            "heavy" => Some(EnemyKind::Heavy),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            EnemyKind::Grunt => "grunt",
+            EnemyKind::Scout => "scout",
+            EnemyKind::Heavy => "heavy",
+        }
+    }
+
+    pub fn max_health(&self) -> f64 {
+        match self {
+            EnemyKind::Grunt => 100.0,
+            EnemyKind::Scout => 60.0,
+            EnemyKind::Heavy => 200.0,
+        }
+    }
+
+    /// Points for killing one (at level 1)
+    pub fn score(&self) -> u32 {
+        match self {
+            EnemyKind::Grunt => 100,
+            EnemyKind::Scout => 150,
+            EnemyKind::Heavy => 300,
+        }
+    }
+
+    pub fn radius(&self) -> f64 {
+        match self {
+            EnemyKind::Grunt => 10.0,
+            EnemyKind::Scout => 8.0,
+            EnemyKind::Heavy => 13.0,
+        }
+    }
+
+    /// Distance walked per frame
+    pub fn speed(&self) -> f64 {
+        match self {
+            EnemyKind::Grunt => 1.2,
+            EnemyKind::Scout => 2.0,
+            EnemyKind::Heavy => 0.8,
+        }
+    }
+
+    /// Width of the sight cone
+    pub fn fov(&self) -> f64 {
+        match self {
+            EnemyKind::Grunt => PI / 3.0,
+            EnemyKind::Scout => PI / 2.0,
+            EnemyKind::Heavy => PI / 4.0,
+        }
+    }
+
+    /// Fastest turn (radians per frame)
+    pub fn turn_rate(&self) -> f64 {
+        match self {
+            EnemyKind::Grunt => PI / 60.0,
+            EnemyKind::Scout => PI / 40.0,
+            EnemyKind::Heavy => PI / 90.0,
+        }
+    }
+
+    /// How far it sees an opponent
+    pub fn view_range(&self) -> f64 {
+        match self {
+            EnemyKind::Grunt => 350.0,
+            EnemyKind::Scout => 450.0,
+            EnemyKind::Heavy => 300.0,
+        }
+    }
+
+    pub fn damage(&self) -> f64 {
+        match self {
+            EnemyKind::Grunt => 8.0,
+            EnemyKind::Scout => 5.0,
+            EnemyKind::Heavy => 15.0,
+        }
+    }
+
+    pub fn range(&self) -> f64 {
+        match self {
+            EnemyKind::Grunt => 400.0,
+            EnemyKind::Scout => 300.0,
+            EnemyKind::Heavy => 500.0,
+        }
+    }
+
+    /// Frames between two shots
+    pub fn fire_rate(&self) -> u64 {
+        match self {
+            EnemyKind::Grunt => 20,
+            EnemyKind::Scout => 10,
+            EnemyKind::Heavy => 40,
+        }
+    }
+
+    /// Largest aim error (radians)
+    pub fn spread(&self) -> f64 {
+        match self {
+            EnemyKind::Grunt => PI / 40.0,
+            EnemyKind::Scout => PI / 24.0,
+            EnemyKind::Heavy => PI / 60.0,
+        }
+    }
+}
 
 pub struct Enemy {
+    pub kind: EnemyKind,
     pub pos: Vector<f64>,
     pub radius: f64,
+    pub max_health: f64,
+    pub health: f64,
+    pub speed: f64,             // distance walked per frame
+    pub fire_cooldown: u64,     // frames before it can shoot again
+    pub brain: Option<Brain>,   // behaviour tree (stands still without one)
+    pub perception: Perception, // heading, sight cone, hearing and memory
+    pub squad: Option<usize>,   // id of its squad
+    pub role: Role,             // what the squad asks of it
+    pub hit_by_player: bool,    // the player dealt the last damage (credited for the kill)
 }
 
 fn sign(x: f64) -> f64 {
@@ -16,12 +154,82 @@ fn sign(x: f64) -> f64 {
 
 impl Enemy {
     pub fn new(x: f64, y: f64) -> Enemy {
+        Enemy::with_kind(EnemyKind::Grunt, Vector::new(x, y, 0.0))
+    }
+
+    pub fn with_kind(kind: EnemyKind, pos: Vector<f64>) -> Enemy {
         Enemy {
-            pos: Vector::new(x, y, 0.0),
-            radius: 10.0,
+            kind,
+            pos,
+            radius: kind.radius(),
+            max_health: kind.max_health(),
+            health: kind.max_health(),
+            speed: kind.speed(),
+            fire_cooldown: 0,
+            brain: None,
+            perception: Perception::new(kind.fov(), kind.view_range()),
+            squad: None,
+            role: Role::Free,
+            hit_by_player: false,
         }
     }
 
+    /// Turns towards a point by at most the turn rate of its kind, returns true once facing it
+    pub fn turn_towards(&mut self, target: Vector<f64>) -> bool {
+        let to = target - self.pos;
+        if to.length_squared() < 1e-8 {
+            return true;
+        }
+        let heading = self.perception.heading;
+        let mut diff = to.y.atan2(to.x) - heading.y.atan2(heading.x);
+        if diff > PI {
+            diff -= 2.0 * PI;
+        } else if diff < -PI {
+            diff += 2.0 * PI;
+        }
+        let max_turn = self.kind.turn_rate();
+        self.perception.heading = heading.rotate_z(diff.clamp(-max_turn, max_turn));
+        f64::abs(diff) <= max_turn
+    }
+
+    /// Runs the behaviour tree for one frame, returns the direction of the shot fired (if any)
+    pub fn think(&mut self, senses: &Senses, rng: &mut impl Rng) -> Option<Vector<f64>> {
+        self.fire_cooldown = self.fire_cooldown.saturating_sub(1);
+        let mut brain = self.brain.take()?;
+        let shot = brain.tick(self, senses, rng);
+        self.brain = Some(brain);
+        shot
+    }
+
+    /// Walks towards a point, sliding along the walls in the way
+    pub fn step_towards(&mut self, to: Vector<f64>, map: &Map) {
+        let d = to - self.pos;
+        let len = d.length_squared().sqrt();
+        if len < 1e-6 {
+            return;
+        }
+        let step = d * (f64::min(self.speed, len) / len);
+        let steps = [
+            step,
+            Vector::new(step.x, 0.0, 0.0),
+            Vector::new(0.0, step.y, 0.0),
+        ];
+        if let Some(&s) = steps
+            .iter()
+            .find(|&&s| map.is_free(self.pos + s, self.radius))
+        {
+            self.pos += s;
+        }
+    }
+
+    pub fn take_damage(&mut self, amount: f64) {
+        self.health = f64::max(self.health - amount, 0.0);
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.health <= 0.0
+    }
+
     /// Only draw part of the enemy that is visible on the screen
     pub fn draw(
         &mut self,
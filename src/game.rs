@@ -1,37 +1,119 @@
-use piston_window::*;
-// use rand::Rng;
 use math_vector::Vector;
-use serde_json::{from_str, Value};
+use piston_window::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use ray_tracing::{Ray as RayTracingRay, Wall as RayTracingWall};
 
-use super::{enemy::*, map::wall::Wall, map::*, player::*};
+use super::{
+    ai::{
+        cover::CoverMap,
+        influence::InfluenceMap,
+        squad::{assign_squads, Role, Squad},
+        Behaviours, Brain, Senses,
+    },
+    bot::{Bot, Difficulty},
+    camera::{Camera, CameraMode},
+    cli::DebugFlags,
+    enemy::*,
+    hero::Hero,
+    hud::Hud,
+    input::{Action, InputEvent, InputMap},
+    map::{element::ElementKind, spawn::SpawnKind, *},
+    minimap::Minimap,
+    nav::NavGrid,
+    player::*,
+    projectile::*,
+    sound::{SoundEvent, SoundKind},
+    watch::Watch,
+    wave::Waves,
+    weapon::Catalogue,
+};
 use crate::{HEIGHT, PI, WIDTH};
 
 pub struct Game {
-    pub player: Player,        // you
-    pub enemies: Vec<Enemy>,   // bad guys
-    pub map: Map,              // the map
-    pub key_pressed: Vec<Key>, // all pressed keys
+    pub player: Player,                  // you
+    pub enemies: Vec<Enemy>,             // bad guys
+    pub bots: Vec<Bot>,                  // computer controlled players (allies and opponents)
+    pub nav: Option<NavGrid>,            // walkable grid for path finding (bots and enemies)
+    pub cover: Option<CoverMap>,         // cover points of the map (with the walkable grid)
+    pub influence: Vec<InfluenceMap>,    // danger for each side (with the walkable grid)
+    pub behaviours: Behaviours,          // behaviour trees of the enemy kinds (idle without)
+    pub squads: Vec<Squad>,              // groups of enemies working together
+    pub map: Map,                        // the map
+    pub map_watch: Option<Watch>,        // file of the map, reloaded when it changes
+    pub input: InputMap,                 // key bindings and state of the inputs
+    pub aim_target: Option<Vector<f64>>, // world point the player turns to (mouse aiming)
+    pub weapons: Catalogue,              // stats of the weapons
 
     pub score: u32, // your score
     pub level: u32, // current level
+    pub kills: u32, // enemies killed
+
+    pub waves: Option<Waves>, // enemies to come (none: only the ones spawned by hand)
+    pub level_cleared: bool,  // all the waves of the level were cleared
+    pub finished: bool,       // all the levels were cleared
 
-    pub game_over: bool,            // game over?
-    pub rng: rand::rngs::ThreadRng, // random number generator
+    pub game_over: bool, // game over?
+    pub seed: u64,       // seed of the random number generator
+    pub rng: StdRng,     // random number generator
+    pub frame: u64,      // number of updates since the start
+
+    pub sounds: Vec<SoundEvent>, // sounds emitted during the current frame
+    pub projectiles: Vec<Projectile>, // grenades, rockets, ... in flight
+    pub explosions: Vec<Explosion>, // explosion effects
+
+    pub hud: Hud,                      // overlay drawn on top of the world
+    pub minimap: Minimap,              // explored part of the map
+    pub screen: [f64; 2],              // size of the window
+    pub debug: DebugFlags,             // debug overlays
+    pub camera: Camera,                // what the screen shows
+    pub errors: Vec<(String, String)>, // data files that could not be (re)loaded, and why
 }
 
 impl Game {
     pub fn new() -> Game {
+        Game::with_seed(rand::random())
+    }
+
+    /// Same as `new` but with a given seed, so that games can be replayed
+    pub fn with_seed(seed: u64) -> Game {
         Game {
             player: Player::new(),
             enemies: Vec::new(),
+            bots: Vec::new(),
+            nav: None,
+            cover: None,
+            influence: Vec::new(),
+            behaviours: Behaviours::new(),
+            squads: Vec::new(),
             map: Map::new(),
-            key_pressed: Vec::new(),
+            map_watch: None,
+            input: InputMap::azerty(),
+            aim_target: None,
+            weapons: Catalogue::new(),
 
             score: 0,
             level: 1,
+            kills: 0,
+
+            waves: None,
+            level_cleared: false,
+            finished: false,
 
             game_over: false,
-            rng: rand::thread_rng(),
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            frame: 0,
+
+            sounds: Vec::new(),
+            projectiles: Vec::new(),
+            explosions: Vec::new(),
+
+            hud: Hud::new(),
+            minimap: Minimap::new(),
+            screen: [WIDTH as f64, HEIGHT as f64],
+            debug: DebugFlags::default(),
+            camera: Camera::new(),
+            errors: Vec::new(),
         }
     }
 
@@ -40,68 +122,763 @@ impl Game {
         self.enemies.push(enemy);
     }
 
-    pub fn add_key_pressed(&mut self, key: Key) {
-        if !self.key_pressed.contains(&key) {
-            self.key_pressed.push(key);
+    /// Spawn enemies on the enemy spawn points of the map (in turn)
+    pub fn spawn_enemies(&mut self, n: usize, kind: EnemyKind) {
+        let spawns = self.map.spawns_of(SpawnKind::Enemy);
+        for i in 0..n {
+            let pos = match spawns.get(i % spawns.len().max(1)) {
+                Some(&pos) => pos,
+                None => Vector::new(300.0, -300.0 + 30.0 * i as f64, 0.0),
+            };
+            self.enemies.push(Enemy::with_kind(kind, pos));
         }
     }
 
-    pub fn remove_key_pressed(&mut self, key: Key) {
-        if self.key_pressed.contains(&key) {
-            self.key_pressed.retain(|&x| x != key);
+    /// Puts a weapon of the catalogue in the hands of the player, false if there is no such weapon
+    pub fn equip(&mut self, name: &str) -> bool {
+        match self.weapons.get(name) {
+            Some(weapon) => {
+                weapon.apply(&mut self.player);
+                true
+            }
+            None => false,
         }
     }
 
-    pub fn load_map(&mut self, path: &str) {
-        self.map = Map::new();
-        let data = std::fs::read_to_string(path)
-            .unwrap_or_else(|_| panic!("Could not read file {}", path));
-        let v: Value = from_str(&data).unwrap_or_else(|_| panic!("Could not parse file {}", path));
+    /// Replace the behaviour trees, the enemies start over with the new ones
+    pub fn set_behaviours(&mut self, behaviours: Behaviours) {
+        self.behaviours = behaviours;
+        for e in self.enemies.iter_mut() {
+            e.brain = None;
+        }
+        self.refresh_nav();
+    }
 
-        let walls = v["walls"].as_array().unwrap().to_vec();
+    /// Gives the player and the bots the new stats of their weapons after the catalogue changed,
+    /// fails if the weapon of the player is not in the catalogue anymore
+    pub fn rearm(&mut self) -> Result<(), String> {
+        for b in self.bots.iter_mut() {
+            if let Some(weapon) = self.weapons.get(&b.player.weapon) {
+                weapon.apply(&mut b.player);
+            }
+        }
+        let name = self.player.weapon.clone();
+        if !self.equip(&name) {
+            return Err(format!("the weapon of the player ({}) is missing", name));
+        }
+        Ok(())
+    }
 
-        for w in walls {
-            let p1 = Vector::new(
-                w["p1"][0].as_f64().unwrap(),
-                w["p1"][1].as_f64().unwrap(),
-                0.0,
-            );
-            let p2 = Vector::new(
-                w["p2"][0].as_f64().unwrap(),
-                w["p2"][1].as_f64().unwrap(),
-                0.0,
-            );
-            let wall = Wall::new(p1, p2);
-            self.map.walls.push(wall);
+    /// Keeps the error of a data file to show it on screen (or forgets it once the file is fine)
+    pub fn report(&mut self, file: &str, result: Result<(), String>) {
+        self.errors.retain(|(f, _)| f != file);
+        if let Err(e) = result {
+            self.errors.push((file.to_string(), e));
         }
     }
 
-    pub fn update(&mut self) {
-        // update player position and heading based on key pressed
-        for key in self.key_pressed.iter() {
-            match key {
-                Key::Up => self.player.move_player(1.0),
-                Key::Z => self.player.move_player(1.0),
-                Key::Down => self.player.move_player(-1.0),
-                Key::S => self.player.move_player(-1.0),
-                Key::Left => self.player.turn_player(-1.0),
-                Key::Right => self.player.turn_player(1.0),
-                Key::Q => self.player.slide_player(-1.0),
-                Key::D => self.player.slide_player(1.0),
+    /// Picks up the changes of the map and weapon catalogue files
+    fn reload_files(&mut self) {
+        match self.weapons.reload() {
+            Ok(false) => (),
+            Ok(true) => {
+                let rearmed = self.rearm();
+                if rearmed.is_ok() {
+                    self.hud.push_kill("weapons reloaded".to_string());
+                }
+                self.report("weapons", rearmed);
+            }
+            Err(e) => self.report("weapons", Err(e)),
+        }
+
+        let path = match self.map_watch.as_mut() {
+            Some(watch) => {
+                if !watch.changed() {
+                    return;
+                }
+                watch.path.clone()
+            }
+            None => return,
+        };
+        let reloaded = self.reload_map(&path);
+        if reloaded.is_ok() {
+            self.hud.push_kill("map reloaded".to_string());
+        }
+        self.report("map", reloaded);
+    }
+
+    /// Replaces the map by a new version of its file, the player stays where it is if it still can
+    fn reload_map(&mut self, path: &str) -> Result<(), String> {
+        self.map = Map::load(path)?;
+        self.minimap.reset(); // the walls changed
+        if !self.map.is_free(self.player.pos, self.player.radius) {
+            if let Some(&pos) = self.map.spawns_of(SpawnKind::Player).first() {
+                self.player.pos = pos;
+            }
+        }
+        for i in 0..self.bots.len() {
+            let p = &self.bots[i].player;
+            if !self.map.is_free(p.pos, p.radius) {
+                let pos = self.bot_spawn_point(self.bots[i].side, i);
+                self.bots[i].reset(pos);
+            }
+        }
+        self.refresh_nav();
+        Ok(())
+    }
+
+    /// Start the waves (and load the map of the first level if it has one)
+    pub fn start_waves(&mut self, mut waves: Waves) -> Result<(), String> {
+        if let Some(map) = waves.current_map() {
+            let map = map.to_string();
+            self.load_map(&map)?;
+        }
+        waves.start_wave();
+        self.level = waves.level as u32 + 1;
+        self.waves = Some(waves);
+        Ok(())
+    }
+
+    /// Go to the next level (and its map, the current one is kept if it does not load), false if there is none
+    pub fn next_level(&mut self) -> bool {
+        let waves = match self.waves.as_mut() {
+            Some(waves) => waves,
+            None => return false,
+        };
+        if !waves.next_level() {
+            self.finished = true;
+            return false;
+        }
+        self.level = waves.level as u32 + 1;
+        self.level_cleared = false;
+        if let Some(map) = waves.current_map() {
+            let map = map.to_string();
+            let loaded = self.load_map(&map);
+            self.report("map", loaded);
+        }
+        true
+    }
+
+    /// Enemy spawn point of the map away from the player (picked at random)
+    fn enemy_spawn_point(&mut self) -> Vector<f64> {
+        let spawns = self.map.spawns_of(SpawnKind::Enemy);
+        let far = spawns
+            .iter()
+            .copied()
+            .filter(|p| p.distance(self.player.pos) > 200.0)
+            .collect::<Vec<_>>();
+        let candidates = if far.is_empty() { spawns } else { far };
+        if candidates.is_empty() {
+            return Vector::new(300.0, -300.0, 0.0);
+        }
+        candidates[self.rng.gen_range(0..candidates.len())]
+    }
+
+    /// Spawn the enemies of the current wave and move on when it is cleared
+    fn update_waves(&mut self) {
+        let (kind, difficulty) = match self.waves.as_mut() {
+            Some(waves) if !self.level_cleared => (waves.update(), waves.difficulty()),
+            _ => return,
+        };
+        if let Some(kind) = kind {
+            let pos = self.enemy_spawn_point();
+            let mut enemy = Enemy::with_kind(kind, pos);
+            enemy.max_health *= difficulty;
+            enemy.health = enemy.max_health;
+            self.enemies.push(enemy);
+        }
+
+        let alive = self.enemies.len();
+        if let Some(waves) = self.waves.as_mut() {
+            if waves.is_wave_cleared(alive) {
+                if waves.next_wave() {
+                    let msg = format!("wave {}/{}", waves.wave + 1, waves.waves_in_level());
+                    self.hud.push_kill(msg);
+                } else {
+                    self.level_cleared = true;
+                }
+            }
+        }
+    }
+
+    pub fn handle_input(&mut self, input: InputEvent) {
+        match input {
+            InputEvent::Press(button) => self.press(button),
+            InputEvent::Release(button) => self.release(button),
+            InputEvent::Cursor(pos) => self.move_cursor(pos),
+            InputEvent::Axis(args) => self.move_axis(args),
+        }
+    }
+
+    pub fn press(&mut self, button: Button) {
+        // things that happen only once per press
+        for action in self.input.press(button) {
+            match action {
+                Action::Interact
+                    if self.map.interact(self.player.pos, self.player.radius * 3.0) =>
+                {
+                    self.emit_sound(SoundKind::Door, self.player.pos, Some(0));
+                }
+                Action::MinimapZoom => self.minimap.cycle_zoom(),
+                Action::Camera => self.camera.cycle_mode(),
                 _ => (),
             }
         }
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.input.release(button);
+    }
+
+    pub fn move_axis(&mut self, args: ControllerAxisArgs) {
+        self.input.move_axis(args);
+    }
+
+    /// Mouse aiming: the point under the cursor becomes the target of the heading
+    pub fn move_cursor(&mut self, pos: [f64; 2]) {
+        let aim = match self.input.mouse_aim {
+            Some(aim) => aim,
+            None => return,
+        };
+        let to = self.screen_to_world(pos) - self.player.pos;
+        // angle between the heading and the cursor, scaled by the sensitivity
+        let h = self.player.heading;
+        let offset = f64::atan2(h.x * to.y - h.y * to.x, h.x * to.x + h.y * to.y);
+        let dir = to.rotate_z(offset * (aim.sensitivity - 1.0));
+        self.aim_target = Some(self.player.pos + dir);
+    }
+
+    /// Position on the screen of a world point (see `Camera`)
+    pub fn world_to_screen(&self, pos: Vector<f64>) -> [f64; 2] {
+        self.camera.world_to_screen(pos)
+    }
+
+    /// World point under a position of the screen (see `Camera`)
+    pub fn screen_to_world(&self, pos: [f64; 2]) -> Vector<f64> {
+        self.camera.screen_to_world(pos)
+    }
+
+    /// A sound heard this frame, `emitter` is the index in `targets` of who made it
+    pub fn emit_sound(&mut self, kind: SoundKind, pos: Vector<f64>, emitter: Option<usize>) {
+        self.sounds.push(SoundEvent::new(kind, pos, emitter));
+    }
+
+    /// Load a map and watch its file for changes
+    pub fn load_map(&mut self, path: &str) -> Result<(), String> {
+        self.map = Map::load(path)?;
+        self.map_watch = Some(Watch::new(path));
+        self.minimap.reset();
+
+        // start on the first player spawn point (if any)
+        if let Some(&pos) = self.map.spawns_of(SpawnKind::Player).first() {
+            self.player.pos = pos;
+        }
+        self.camera.cut();
+        for i in 0..self.bots.len() {
+            let pos = self.bot_spawn_point(self.bots[i].side, i);
+            self.bots[i].reset(pos);
+        }
+        self.refresh_nav();
+        Ok(())
+    }
+
+    /// Adds a bot with the stats and the weapon of a hero
+    pub fn add_bot(&mut self, side: Owner, difficulty: Difficulty, hero: &Hero) {
+        let name = format!("bot-{}", self.bots.len() + 1);
+        let pos = self.bot_spawn_point(side, self.bots.len());
+        let mut bot = Bot::new(&name, side, difficulty, pos);
+        hero.apply(&mut bot.player);
+        if let Some(weapon) = self.weapons.get(&hero.weapon) {
+            weapon.apply(&mut bot.player);
+        }
+        self.bots.push(bot);
+        if self.nav.is_none() {
+            self.refresh_nav();
+        }
+    }
+
+    /// Where the `i`-th bot starts: allies on the other player spawn points (or around the player),
+    /// opponents around the enemy spawn points
+    fn bot_spawn_point(&mut self, side: Owner, i: usize) -> Vector<f64> {
+        let ally = self.bots[..i.min(self.bots.len())]
+            .iter()
+            .filter(|b| b.side == Owner::Player)
+            .count();
+        let center = match side {
+            Owner::Enemy => self.enemy_spawn_point(),
+            Owner::Player => match self.map.spawns_of(SpawnKind::Player).get(ally + 1) {
+                Some(&pos) => return pos,
+                None => self.player.pos,
+            },
+        };
+        // first free spot on a circle around the center, not on top of the others
+        let r = self.player.radius * 3.0;
+        (0..12)
+            .map(|k| (i + k) as f64 * PI / 6.0)
+            .map(|a| center + Vector::new(a.cos(), a.sin(), 0.0) * r)
+            .find(|&pos| self.map.is_free(pos, self.player.radius))
+            .unwrap_or(center)
+    }
+
+    /// Walkable grid, cover points and danger for the bots and enemies, built again for the current map
+    fn refresh_nav(&mut self) {
+        if self.bots.is_empty() && self.behaviours.is_empty() {
+            self.nav = None;
+            self.cover = None;
+            self.influence.clear();
+        } else {
+            let nav = NavGrid::new(&self.map, 20.0, self.player.radius);
+            self.influence = [Owner::Player, Owner::Enemy]
+                .into_iter()
+                .map(|side| InfluenceMap::new(side, &nav))
+                .collect();
+            self.nav = Some(nav);
+            self.cover = Some(CoverMap::new(&self.map, self.player.radius));
+        }
+    }
+
+    /// Everything that can be hit: the player, then the bots, then the enemies
+    pub fn targets(&self) -> Vec<Target> {
+        let player = |p: &Player, side: Owner| Target {
+            side,
+            pos: p.pos,
+            radius: p.radius,
+            health: p.health / p.max_health,
+        };
+        let mut targets = vec![player(&self.player, Owner::Player)];
+        targets.extend(self.bots.iter().map(|b| player(&b.player, b.side)));
+        targets.extend(self.enemies.iter().map(|e| Target {
+            side: Owner::Enemy,
+            pos: e.pos,
+            radius: e.radius,
+            health: e.health / e.max_health,
+        }));
+        targets
+    }
+
+    /// Damages one of the `targets` (the place gets dangerous for its side),
+    /// the shooter is remembered to credit the kills of the player
+    pub fn damage_target(&mut self, i: usize, amount: f64, shooter: Shooter) {
+        if amount <= 0.0 {
+            return; // edge of a blast
+        }
+        let by_player = shooter == Shooter::Player;
+        let bots = self.bots.len();
+        let (side, pos) = match i {
+            0 => {
+                self.player.take_damage(amount);
+                self.camera.shake(amount);
+                (Owner::Player, self.player.pos)
+            }
+            i if i <= bots => {
+                let b = &mut self.bots[i - 1];
+                b.player.take_damage(amount);
+                b.hit_by_player = by_player;
+                (b.side, b.player.pos)
+            }
+            i => {
+                let e = &mut self.enemies[i - 1 - bots];
+                e.take_damage(amount);
+                e.hit_by_player = by_player;
+                (Owner::Enemy, e.pos)
+            }
+        };
+        if let Some(nav) = self.nav.as_ref() {
+            for m in self.influence.iter_mut().filter(|m| m.side == side) {
+                m.add_damage(nav, pos, amount);
+            }
+        }
+    }
+
+    /// Danger maps: what each side knows of its opponents, where it got hurt and the fighting heard
+    fn update_influence(&mut self) {
+        let nav = match self.nav.as_ref() {
+            Some(nav) => nav,
+            None => return,
+        };
+        let walls = self
+            .map
+            .walls
+            .iter()
+            .map(|w| w.to_rt())
+            .collect::<Vec<RayTracingWall>>();
+        let targets = self.targets();
+        for m in self.influence.iter_mut() {
+            let mut threats = self
+                .bots
+                .iter()
+                .filter(|b| b.side == m.side)
+                .filter_map(|b| b.last_seen)
+                .collect::<Vec<Vector<f64>>>();
+            match m.side {
+                Owner::Player => threats.extend(
+                    targets
+                        .iter()
+                        .filter(|t| t.side == Owner::Enemy && self.player.sees(t.pos, t.radius))
+                        .map(|t| t.pos),
+                ),
+                Owner::Enemy => threats.extend(
+                    self.enemies
+                        .iter()
+                        .filter_map(|e| e.perception.memory)
+                        .map(|memory| memory.pos),
+                ),
+            }
+            m.update(nav, &threats, &self.sounds, &walls);
+        }
+    }
+
+    /// The enemies work in squads and run their behaviour tree (shooting at the player and its allies)
+    fn update_enemies(&mut self) {
+        if self.behaviours.is_empty() {
+            return;
+        }
+        for e in self.enemies.iter_mut().filter(|e| e.brain.is_none()) {
+            e.brain = self.behaviours.get(e.kind.name()).map(Brain::new);
+        }
+        assign_squads(&mut self.squads, &mut self.enemies);
+        for squad in self.squads.iter_mut() {
+            let mut members = self
+                .enemies
+                .iter_mut()
+                .filter(|e| e.squad == Some(squad.id))
+                .collect::<Vec<&mut Enemy>>();
+            squad.update(&mut members, self.nav.as_ref());
+        }
+
+        let targets = self.targets();
+        let walls = self
+            .map
+            .walls
+            .iter()
+            .map(|w| w.to_rt())
+            .collect::<Vec<RayTracingWall>>();
+        let first = 1 + self.bots.len(); // index of the first enemy in the targets
+        let senses = Senses {
+            targets: &targets,
+            own: first,
+            sounds: &self.sounds,
+            map: &self.map,
+            walls: &walls,
+            nav: self.nav.as_ref(),
+            cover: self.cover.as_ref(),
+            danger: self.influence.iter().find(|m| m.side == Owner::Enemy),
+        };
+        let mut shots = Vec::new();
+        for (j, e) in self.enemies.iter_mut().enumerate() {
+            let senses = Senses {
+                own: first + j,
+                ..senses
+            };
+            if let Some(dir) = e.think(&senses, &mut self.rng) {
+                shots.push((first + j, e.pos, dir, e.kind));
+            }
+        }
+        for (own, pos, dir, kind) in shots {
+            self.emit_sound(SoundKind::Shot, pos, Some(own));
+            self.shoot(pos, dir, kind.damage(), kind.range(), 1.0, Shooter::Enemy);
+        }
+    }
+
+    /// The bots perceive, think and play with the player controls
+    fn update_bots(&mut self) {
+        if self.bots.is_empty() {
+            return;
+        }
+        let targets = self.targets();
+        let walls = self
+            .map
+            .walls
+            .iter()
+            .map(|w| w.to_rt())
+            .collect::<Vec<RayTracingWall>>();
+        for i in 0..self.bots.len() {
+            let bot = &mut self.bots[i];
+            let senses = Senses {
+                targets: &targets,
+                own: i + 1,
+                sounds: &self.sounds,
+                map: &self.map,
+                walls: &walls,
+                nav: self.nav.as_ref(),
+                cover: self.cover.as_ref(),
+                danger: self.influence.iter().find(|m| m.side == bot.side),
+            };
+            let actions = bot.think(&senses, &mut self.rng);
+            for &(action, value) in actions.iter() {
+                bot.player.act(action, value);
+            }
+            if let Some(aim) = bot.aim {
+                bot.player.turn_towards(aim, bot.difficulty.max_turn());
+            }
+            if actions.iter().any(|(a, _)| *a == Action::Fire) {
+                let shooter = Shooter::Bot(bot.side);
+                for shot in bot.player.fire(&mut self.rng) {
+                    self.fire_shot(shot, shooter, i + 1);
+                }
+            }
+
+            let bot = &mut self.bots[i];
+            bot.player.collide(&self.map.walls);
+            bot.player.update_player();
+            bot.player.compute_view(&self.map.walls);
+            for kind in pick_up(&mut bot.player, &mut self.map, false) {
+                if let ElementKind::Weapon { name, .. } = kind {
+                    if let Some(weapon) = self.weapons.get(&name) {
+                        weapon.apply(&mut bot.player);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn update(&mut self) {
+        self.frame += 1;
+        if self.frame.is_multiple_of(30) {
+            self.reload_files();
+        }
+
+        // update player position and heading based on the actions (they move the spectator camera)
+        let free = self.camera.mode == CameraMode::Free;
+        for action in Action::ALL {
+            let value = self.input.value(action);
+            if value > 0.0 && !(free && action.is_movement()) {
+                self.player.act(action, value);
+            }
+        }
+        if let (Some(target), Some(aim)) = (self.aim_target, self.input.mouse_aim) {
+            if self.player.turn_towards(target, aim.max_turn) {
+                self.aim_target = None; // let the keys turn again
+            }
+        }
+        if self.input.is_active(Action::Fire) {
+            for shot in self.player.fire(&mut self.rng) {
+                self.fire_shot(shot, Shooter::Player, 0);
+            }
+        }
+
+        if self.input.is_active(Action::Ability) {
+            if let Some(dir) = self.player.throw() {
+                let pos = self.player.pos + dir * self.player.radius;
+                self.launch(ProjectileKind::Grenade, Shooter::Player, pos, dir, 0);
+            }
+        }
+
+        self.player.collide(&self.map.walls);
         self.player.update_player();
-        self.player.compute_view(&self.map.walls)
+        self.player.compute_view(&self.map.walls);
+        let pan = if free {
+            let value = |a: Action| self.input.value(a);
+            Vector::new(
+                value(Action::StrafeRight) - value(Action::StrafeLeft),
+                value(Action::MoveBackward) - value(Action::MoveForward),
+                0.0,
+            )
+        } else {
+            Vector::default()
+        };
+        self.camera.update(&self.player, self.screen, pan);
+
+        // pick up the elements the player is walking on
+        for kind in pick_up(&mut self.player, &mut self.map, true) {
+            match kind {
+                ElementKind::Trigger(id) => self.map.trigger(id),
+                ElementKind::Weapon { name, .. } => {
+                    self.equip(&name);
+                }
+                _ => (),
+            }
+        }
+        self.update_bots();
+        self.update_enemies();
+        self.update_projectiles();
+        // only the kills of the player count, not the ones of its allies
+        for e in self.enemies.iter().filter(|e| e.is_dead()) {
+            if e.hit_by_player {
+                let points = e.kind.score() * self.level;
+                self.hud
+                    .push_kill(format!("you killed a {} (+{})", e.kind.name(), points));
+                self.score += points;
+                self.kills += 1;
+            } else {
+                self.hud
+                    .push_kill(format!("a {} was killed", e.kind.name()));
+            }
+        }
+        for b in self.bots.iter().filter(|b| b.player.is_dead()) {
+            if b.side == Owner::Enemy && b.hit_by_player {
+                let points = 200 * self.level;
+                self.hud
+                    .push_kill(format!("{} is down (+{})", b.name, points));
+                self.score += points;
+                self.kills += 1;
+            } else {
+                self.hud.push_kill(format!("{} is down", b.name));
+            }
+        }
+        if self.player.is_dead() {
+            self.game_over = true;
+        }
+        self.enemies.retain(|e| !e.is_dead());
+        self.bots.retain(|b| !b.player.is_dead());
+        self.update_waves();
+        self.hud.update();
+        self.update_influence();
+        self.minimap.update(&self.player, &self.map, &self.sounds);
+        self.sounds.clear();
+        if self.map.dirty {
+            if let Some(nav) = self.nav.as_mut() {
+                nav.rebuild(&self.map); // doors and broken walls
+            }
+            if self.cover.is_some() {
+                self.cover = Some(CoverMap::new(&self.map, self.player.radius));
+            }
+        }
+        self.map.update();
     }
 
-    pub fn draw(&mut self, c: &Context, g: &mut G2d, _glyphs: &mut Glyphs) {
-        let (cx, cy) = (WIDTH as f64 / 2.0, HEIGHT as f64 / 2.0);
-        let (x0, y0) = (self.player.pos.x, self.player.pos.y);
+    /// Fires a projectile (`emitter` is the index of the shooter in `targets`)
+    pub fn launch(
+        &mut self,
+        kind: ProjectileKind,
+        shooter: Shooter,
+        pos: Vector<f64>,
+        dir: Vector<f64>,
+        emitter: usize,
+    ) {
+        self.projectiles
+            .push(Projectile::new(kind, shooter, pos, dir));
+        self.emit_sound(SoundKind::Shot, pos, Some(emitter));
+    }
 
-        let mut transform = c.transform.trans(cx, cy); // translate to center
-        transform = transform.rot_rad(-self.player.heading.angle() - PI / 2.0); // rotate
-        transform = transform.trans(-x0, -y0); // translate to player
+    /// A bullet (hitscan) or a projectile of the weapon, the weapon gives the damage of a direct hit
+    /// (`emitter` is the index of the shooter in `targets`)
+    fn fire_shot(&mut self, shot: Shot, shooter: Shooter, emitter: usize) {
+        self.emit_sound(SoundKind::Shot, shot.pos, Some(emitter));
+        match shot.projectile {
+            Some(kind) => {
+                let mut p = Projectile::new(kind, shooter, shot.pos, shot.dir);
+                p.damage = shot.damage;
+                self.projectiles.push(p);
+            }
+            None => self.shoot(
+                shot.pos,
+                shot.dir,
+                shot.damage,
+                shot.range,
+                shot.penetration,
+                shooter,
+            ),
+        }
+    }
+
+    /// Moves the projectiles and applies the damage of the ones that hit something
+    fn update_projectiles(&mut self) {
+        let targets = self.targets();
+        let mut done = Vec::new();
+        for (i, p) in self.projectiles.iter_mut().enumerate() {
+            if let Some(impact) = p.step(&self.map.walls, &targets) {
+                done.push((i, impact));
+            }
+        }
+        for (i, impact) in done.into_iter().rev() {
+            let p = self.projectiles.remove(i);
+            match impact {
+                Impact::Target(t) => self.damage_target(t, p.damage, p.shooter),
+                Impact::Wall(w) => self.map.damage_wall(w, p.damage),
+                Impact::Expired => (),
+            }
+            if p.blast_radius > 0.0 {
+                self.explode(p.pos, p.blast_radius, p.blast_damage, p.shooter);
+            } else {
+                self.emit_sound(SoundKind::Impact, p.pos, None);
+            }
+        }
+
+        for e in self.explosions.iter_mut() {
+            e.age += 1;
+        }
+        self.explosions.retain(|e| !e.is_over());
+    }
+
+    /// Area damage: hurts what the blast reaches (not behind walls), less and less far from the center
+    pub fn explode(&mut self, pos: Vector<f64>, radius: f64, damage: f64, shooter: Shooter) {
+        self.explosions.push(Explosion::new(pos, radius));
+        self.emit_sound(SoundKind::Explosion, pos, None);
+
+        let reached = self
+            .targets()
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| blast_reaches(pos, t.pos, &self.map.walls))
+            .map(|(i, t)| (i, pos.distance(t.pos)))
+            .collect::<Vec<(usize, f64)>>();
+        for (i, d) in reached {
+            self.damage_target(i, blast_damage(damage, radius, d), shooter);
+        }
+        let walls = &self.map.walls;
+        let hit = (0..walls.len())
+            .map(|i| (i, walls[i].distance_to(pos)))
+            .filter(|&(_, d)| d < radius)
+            .collect::<Vec<(usize, f64)>>();
+        for (i, d) in hit {
+            self.map.damage_wall(i, blast_damage(damage, radius, d));
+        }
+    }
+
+    /// Hitscan shot: damages the walls it goes through and the first opponent of the shooter on its way
+    /// (`penetration` scales the damage kept through each wall, the caller emits the sound of the shot)
+    pub fn shoot(
+        &mut self,
+        origin: Vector<f64>,
+        dir: Vector<f64>,
+        damage: f64,
+        range: f64,
+        penetration: f64,
+        shooter: Shooter,
+    ) {
+        let indices = (0..self.map.walls.len())
+            .filter(|&i| self.map.walls[i].blocks_movement())
+            .collect::<Vec<usize>>();
+        let rtwalls = indices
+            .iter()
+            .map(|&i| {
+                let mut wall = self.map.walls[i].to_rt();
+                wall.penetration = f64::min(wall.penetration * penetration, 1.0);
+                wall
+            })
+            .collect::<Vec<RayTracingWall>>();
+
+        let ray = RayTracingRay::new(origin, dir);
+        let hits = ray.penetrate(&rtwalls, damage, range);
+        let stop = match hits.last() {
+            Some(hit) if hit.remaining <= 0.0 => hit.distance,
+            _ => range,
+        };
+
+        let target_hit = self
+            .targets()
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.side != shooter.side())
+            .filter_map(|(i, t)| ray.cast_circle(t.pos, t.radius).map(|d| (i, d)))
+            .filter(|(_, d)| *d <= stop)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        let stop = target_hit.map_or(stop, |(_, d)| d);
+
+        let mut damage = damage;
+        for hit in hits.iter().filter(|h| h.distance <= stop) {
+            self.map.damage_wall(indices[hit.index], hit.damage);
+            self.emit_sound(SoundKind::Impact, hit.point, None);
+            damage = hit.remaining;
+        }
+        if let Some((i, _)) = target_hit {
+            self.damage_target(i, damage, shooter);
+        }
+    }
+
+    pub fn draw(&mut self, c: &Context, g: &mut G2d, glyphs: &mut Glyphs) {
+        let transform = self.camera.transform(c.transform);
 
         self.map.draw(c, g, transform); // draw map
         self.player.draw(c, g, transform); // draw player
@@ -117,6 +894,116 @@ impl Game {
             );
         }
 
-        // self.map.draw_qt(c, g, transform);
+        for b in self.bots.iter() {
+            // opponents only show up when the player can see them
+            if b.side == Owner::Player || self.player.sees(b.player.pos, b.player.radius) {
+                b.draw(c, g, transform);
+            }
+        }
+
+        for p in self.projectiles.iter() {
+            p.draw(c, g, transform);
+        }
+        for e in self.explosions.iter() {
+            e.draw(c, g, transform);
+        }
+
+        if self.debug.spawns {
+            for spawn in self.map.spawns.iter() {
+                spawn.draw(c, g, transform);
+            }
+        }
+        if self.debug.qtree {
+            self.map.draw_qt(c, g, transform);
+        }
+        if self.debug.bots {
+            for b in self.bots.iter() {
+                b.draw_debug(c, g, transform);
+            }
+        }
+        if let Some(nav) = self.nav.as_ref().filter(|_| self.debug.danger) {
+            // what the side of the player fears
+            for m in self.influence.iter().filter(|m| m.side == Owner::Player) {
+                m.draw(c, g, transform, nav);
+            }
+        }
+        if let Some(cover) = self.cover.as_ref().filter(|_| self.debug.cover) {
+            // rated against the player
+            let walls = self
+                .map
+                .walls
+                .iter()
+                .map(|w| w.to_rt())
+                .collect::<Vec<RayTracingWall>>();
+            cover.draw(c, g, transform, self.player.pos, &walls);
+        }
+        if self.debug.ai {
+            for e in self.enemies.iter() {
+                e.perception.draw(c, g, transform, e.pos);
+                let mut from = e.pos;
+                for &p in e.brain.iter().flat_map(|b| b.path.iter()) {
+                    line(
+                        [0.9, 0.5, 0.2, 0.5],
+                        0.5,
+                        [from.x, from.y, p.x, p.y],
+                        transform,
+                        g,
+                    );
+                    from = p;
+                }
+            }
+            // role in the squad and active node of the behaviour tree above each enemy
+            for e in self.enemies.iter() {
+                if let Some(brain) = &e.brain {
+                    let [x, y] = self.world_to_screen(e.pos);
+                    let name = match e.role {
+                        Role::Free => brain.state.active.clone(),
+                        role => format!("{}: {}", role.name(), brain.state.active),
+                    };
+                    let width = glyphs.width(10, &name).unwrap_or(0.0);
+                    text::Text::new_color([0.6, 1.0, 0.6, 1.0], 10)
+                        .draw(
+                            &name,
+                            glyphs,
+                            &c.draw_state,
+                            c.transform.trans(x - width / 2.0, y - e.radius - 6.0),
+                            g,
+                        )
+                        .unwrap_or_else(|e| panic!("Failed to draw text: {}", e));
+                }
+            }
+        }
+
+        self.hud.draw(c, g, glyphs, self);
+        let corner = [20.0, self.screen[1] - 80.0 - self.minimap.size];
+        let allies = self
+            .bots
+            .iter()
+            .filter(|b| b.side == Owner::Player)
+            .map(|b| b.player.pos)
+            .collect::<Vec<Vector<f64>>>();
+        self.minimap
+            .draw(c, g, corner, &self.map, &self.player, &allies);
+    }
+}
+
+/// Picks up the elements the player is walking on, returns their kinds
+/// (triggers are only activated by the human player)
+fn pick_up(player: &mut Player, map: &mut Map, triggers: bool) -> Vec<ElementKind> {
+    let mut picked = Vec::new();
+    for element in map.elements.iter_mut() {
+        let r = player.radius + element.radius;
+        if !element.is_active() || (element.pos - player.pos).length_squared() >= r * r {
+            continue;
+        }
+        let taken = match element.kind {
+            ElementKind::Trigger(_) => triggers,
+            _ => player.pick_up(&element.kind),
+        };
+        if taken {
+            element.pick();
+            picked.push(element.kind.clone());
+        }
     }
+    picked
 }
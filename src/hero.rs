@@ -0,0 +1,57 @@
+use crate::player::Player;
+
+/// Playable character: a set of stats applied to the player
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hero {
+    pub name: String,
+    pub health: f64,
+    pub armor: f64,
+    pub speed: f64,      // linear velocity
+    pub weapon: String,  // starting weapon (name in the weapon catalogue)
+    pub fov_radius: f64, // how far the hero sees
+}
+
+impl Hero {
+    /// Heroes that can be picked in the menu
+    pub fn all() -> Vec<Hero> {
+        vec![
+            Hero {
+                name: "soldier".to_string(),
+                health: 100.0,
+                armor: 0.0,
+                speed: 1.0,
+                weapon: "rifle".to_string(),
+                fov_radius: 200.0,
+            },
+            Hero {
+                name: "scout".to_string(),
+                health: 75.0,
+                armor: 0.0,
+                speed: 1.5,
+                weapon: "smg".to_string(),
+                fov_radius: 250.0,
+            },
+            Hero {
+                name: "tank".to_string(),
+                health: 150.0,
+                armor: 50.0,
+                speed: 0.7,
+                weapon: "marksman".to_string(),
+                fov_radius: 180.0,
+            },
+        ]
+    }
+
+    pub fn from_name(name: &str) -> Option<Hero> {
+        Hero::all().into_iter().find(|h| h.name == name)
+    }
+
+    /// Gives the stats of the hero to a player (full health, the weapon is given by the game)
+    pub fn apply(&self, player: &mut Player) {
+        player.max_health = self.health;
+        player.health = self.health;
+        player.armor = self.armor;
+        player.lin_vel = self.speed;
+        player.fov_radius = self.fov_radius;
+    }
+}
@@ -0,0 +1,180 @@
+use piston_window::*;
+
+use crate::{game::Game, PI};
+
+const TEXT: [f32; 4] = [1.0, 1.0, 1.0, 0.9];
+const BACK: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+
+/// Screen-space overlay: bars, ammo, cooldowns, score, level, wave and kill feed
+pub struct Hud {
+    pub visible: bool,
+    pub kill_feed: Vec<(String, u64)>, // messages and the frames they stay on screen
+    pub feed_time: u64,                // how long a message stays (frames)
+    pub feed_size: usize,              // maximum number of messages
+}
+
+impl Default for Hud {
+    fn default() -> Hud {
+        Hud::new()
+    }
+}
+
+impl Hud {
+    pub fn new() -> Hud {
+        Hud {
+            visible: true,
+            kill_feed: Vec::new(),
+            feed_time: 300,
+            feed_size: 5,
+        }
+    }
+
+    /// Adds a message on top of the kill feed
+    pub fn push_kill(&mut self, message: String) {
+        self.kill_feed.insert(0, (message, self.feed_time));
+        self.kill_feed.truncate(self.feed_size);
+    }
+
+    pub fn update(&mut self) {
+        for (_, t) in self.kill_feed.iter_mut() {
+            *t = t.saturating_sub(1);
+        }
+        self.kill_feed.retain(|(_, t)| *t > 0);
+    }
+
+    /// Draws the HUD with the untransformed context (not rotated with the player)
+    pub fn draw(&self, c: &Context, g: &mut G2d, glyphs: &mut Glyphs, game: &Game) {
+        if !self.visible {
+            return;
+        }
+        let [w, h] = game.screen;
+        let p = &game.player;
+
+        // data files that could not be reloaded (top center)
+        for (i, (_, e)) in game.errors.iter().enumerate() {
+            error(c, g, glyphs, e, w / 2.0, 60.0 + 16.0 * i as f64);
+        }
+
+        // health and armor bars (bottom left)
+        let (x, y) = (20.0, h - 50.0);
+        bar(
+            c,
+            g,
+            [x, y, 150.0, 10.0],
+            p.health / p.max_health,
+            [0.8, 0.1, 0.1, 0.9],
+        );
+        bar(
+            c,
+            g,
+            [x, y + 15.0, 150.0, 6.0],
+            p.armor / p.max_armor,
+            [0.2, 0.4, 0.9, 0.9],
+        );
+        let hp = format!("{:.0} / {:.0}", p.health, p.armor);
+        draw_text(c, g, glyphs, &hp, 12, x + 160.0, y + 10.0);
+
+        // magazine and total ammo (bottom right)
+        let ammo = format!("{} / {}", p.in_mag, p.ammo);
+        let tw = glyphs.width(20, &ammo).unwrap_or(0.0);
+        draw_text(c, g, glyphs, &ammo, 20, w - 20.0 - tw, y + 10.0);
+        let grenades = format!("grenades {}", p.grenades);
+        let tw = glyphs.width(10, &grenades).unwrap_or(0.0);
+        draw_text(c, g, glyphs, &grenades, 10, w - 20.0 - tw, y - 15.0);
+
+        // reload progress, or fire cooldown when not reloading
+        if p.reload_cooldown > 0 {
+            let done = 1.0 - p.reload_cooldown as f64 / p.reload_time.max(1) as f64;
+            bar(
+                c,
+                g,
+                [w - 170.0, y + 15.0, 150.0, 6.0],
+                done,
+                [0.9, 0.8, 0.2, 0.9],
+            );
+            draw_text(c, g, glyphs, "reloading", 10, w - 170.0, y - 15.0);
+        } else {
+            let done = 1.0 - p.fire_cooldown as f64 / p.fire_rate.max(1) as f64;
+            bar(
+                c,
+                g,
+                [w - 170.0, y + 15.0, 150.0, 3.0],
+                done,
+                [0.7, 0.7, 0.7, 0.9],
+            );
+        }
+
+        // cone of fire (bottom center), wider when less accurate
+        let (cx, cy, len) = (w / 2.0, h - 20.0, 40.0);
+        let spread = f64::min(p.accuracy, PI / 2.0);
+        for a in [-spread, spread] {
+            let (s, co) = (a.sin(), a.cos());
+            line(
+                TEXT,
+                1.0,
+                [cx, cy, cx + s * len, cy - co * len],
+                c.transform,
+                g,
+            );
+        }
+        let acc = format!("{:.1}°", p.accuracy.to_degrees());
+        draw_text(c, g, glyphs, &acc, 10, cx + 10.0, cy);
+
+        // score and level (top right)
+        let wave = game.waves.as_ref().map_or(String::new(), |w| {
+            format!("wave {}/{}", w.wave + 1, w.waves_in_level())
+        });
+        for (i, s) in [
+            format!("score {}", game.score),
+            format!("level {}", game.level),
+            wave,
+        ]
+        .iter()
+        .enumerate()
+        {
+            let tw = glyphs.width(14, s).unwrap_or(0.0);
+            draw_text(c, g, glyphs, s, 14, w - 20.0 - tw, 25.0 + 20.0 * i as f64);
+        }
+
+        // kill feed (top left, under the fps), fading out
+        for (i, (msg, t)) in self.kill_feed.iter().enumerate() {
+            let alpha = f64::min(*t as f64 / 60.0, 1.0) as f32;
+            let transform = c.transform.trans(10.0, 45.0 + 16.0 * i as f64);
+            text::Text::new_color([1.0, 0.8, 0.6, alpha], 11)
+                .draw(msg, glyphs, &c.draw_state, transform, g)
+                .unwrap_or_else(|e| panic!("Failed to draw text: {}", e));
+        }
+    }
+}
+
+/// Horizontal bar filled from the left
+fn bar(c: &Context, g: &mut G2d, rect: [f64; 4], fill: f64, color: [f32; 4]) {
+    rectangle(BACK, rect, c.transform, g);
+    let fill = fill.clamp(0.0, 1.0);
+    rectangle(
+        color,
+        [rect[0], rect[1], rect[2] * fill, rect[3]],
+        c.transform,
+        g,
+    );
+}
+
+fn draw_text(c: &Context, g: &mut G2d, glyphs: &mut Glyphs, s: &str, size: u32, x: f64, y: f64) {
+    text::Text::new_color(TEXT, size)
+        .draw(s, glyphs, &c.draw_state, c.transform.trans(x, y), g)
+        .unwrap_or_else(|e| panic!("Failed to draw text: {}", e));
+}
+
+/// Error message in red, centered on `x`
+pub fn error(c: &Context, g: &mut G2d, glyphs: &mut Glyphs, s: &str, x: f64, y: f64) {
+    let width = glyphs.width(12, s).unwrap_or(0.0);
+    text::Text::new_color([1.0, 0.3, 0.3, 1.0], 12)
+        .draw(
+            s,
+            glyphs,
+            &c.draw_state,
+            c.transform.trans(x - width / 2.0, y),
+            g,
+        )
+        .unwrap_or_else(|e| panic!("Failed to draw text: {}", e));
+}
@@ -0,0 +1,532 @@
+use piston_window::{
+    Button, ButtonEvent, ButtonState, ControllerAxisArgs, ControllerAxisEvent, ControllerButton,
+    Event, Key, MouseButton, MouseCursorEvent,
+};
+use serde_json::{json, Map as JsonMap, Value};
+
+use crate::PI;
+
+/// What the player wants to do, independently of the device
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    StrafeLeft,
+    StrafeRight,
+    TurnLeft,
+    TurnRight,
+    Fire,
+    Reload,
+    Interact, // open / close doors
+    Ability,
+    MinimapZoom,
+    Camera, // heading-up, north-up or spectator camera
+}
+
+/// A raw input that can be bound to an action
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Binding {
+    Key(Key),
+    Mouse(MouseButton),
+    Gamepad(u8),                       // button of any controller
+    Axis { axis: u8, positive: bool }, // one half of an axis of any controller
+}
+
+impl Action {
+    pub const ALL: [Action; 12] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::StrafeLeft,
+        Action::StrafeRight,
+        Action::TurnLeft,
+        Action::TurnRight,
+        Action::Fire,
+        Action::Reload,
+        Action::Interact,
+        Action::Ability,
+        Action::MinimapZoom,
+        Action::Camera,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveForward => "forward",
+            Action::MoveBackward => "backward",
+            Action::StrafeLeft => "strafe_left",
+            Action::StrafeRight => "strafe_right",
+            Action::TurnLeft => "turn_left",
+            Action::TurnRight => "turn_right",
+            Action::Fire => "fire",
+            Action::Reload => "reload",
+            Action::Interact => "interact",
+            Action::Ability => "ability",
+            Action::MinimapZoom => "minimap_zoom",
+            Action::Camera => "camera",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().copied().find(|a| a.name() == name)
+    }
+
+    /// Actions that move the player (they move the spectator camera instead)
+    pub fn is_movement(&self) -> bool {
+        matches!(
+            self,
+            Action::MoveForward | Action::MoveBackward | Action::StrafeLeft | Action::StrafeRight
+        )
+    }
+}
+
+impl Binding {
+    /// Parse a binding from the config file: `Space`, `mouse:Left`, `pad:3`, `axis:1-`
+    pub fn parse(s: &str) -> Result<Binding, String> {
+        let invalid = || format!("invalid binding {}", s);
+        let binding = match s.split_once(':') {
+            None => Binding::Key(serde_json::from_value(json!(s)).map_err(|_| invalid())?),
+            Some(("mouse", b)) => {
+                Binding::Mouse(serde_json::from_value(json!(b)).map_err(|_| invalid())?)
+            }
+            Some(("pad", b)) => Binding::Gamepad(b.parse().map_err(|_| invalid())?),
+            Some(("axis", a)) => {
+                let positive = match a.chars().last() {
+                    Some('+') => true,
+                    Some('-') => false,
+                    _ => return Err(invalid()),
+                };
+                let axis = a[..a.len() - 1].parse().map_err(|_| invalid())?;
+                Binding::Axis { axis, positive }
+            }
+            _ => return Err(invalid()),
+        };
+        Ok(binding)
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => variant_name(key),
+            Binding::Mouse(b) => format!("mouse:{}", variant_name(b)),
+            Binding::Gamepad(b) => format!("pad:{}", b),
+            Binding::Axis { axis, positive } => {
+                format!("axis:{}{}", axis, if *positive { '+' } else { '-' })
+            }
+        }
+    }
+
+    /// Button of the binding (the first controller for gamepad buttons), none for axes
+    pub fn button(&self) -> Option<Button> {
+        match *self {
+            Binding::Key(key) => Some(Button::Keyboard(key)),
+            Binding::Mouse(b) => Some(Button::Mouse(b)),
+            Binding::Gamepad(button) => {
+                Some(Button::Controller(ControllerButton { id: 0, button }))
+            }
+            Binding::Axis { .. } => None,
+        }
+    }
+
+    fn from_button(button: Button) -> Option<Binding> {
+        match button {
+            Button::Keyboard(key) => Some(Binding::Key(key)),
+            Button::Mouse(b) => Some(Binding::Mouse(b)),
+            Button::Controller(ControllerButton { button, .. }) => Some(Binding::Gamepad(button)),
+            _ => None,
+        }
+    }
+}
+
+/// Raw input of the window that matters to the game (also what replays are made of)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputEvent {
+    Press(Button),
+    Release(Button),
+    Cursor([f64; 2]), // position of the mouse in the window
+    Axis(ControllerAxisArgs),
+}
+
+impl InputEvent {
+    pub fn from_event(event: &Event) -> Option<InputEvent> {
+        if let Some(args) = event.button_args() {
+            return Some(match args.state {
+                ButtonState::Press => InputEvent::Press(args.button),
+                ButtonState::Release => InputEvent::Release(args.button),
+            });
+        }
+        if let Some(pos) = event.mouse_cursor_args() {
+            return Some(InputEvent::Cursor(pos));
+        }
+        event.controller_axis_args().map(InputEvent::Axis)
+    }
+
+    pub fn to_value(&self) -> Value {
+        fn value(v: &impl serde::Serialize) -> Value {
+            serde_json::to_value(v).unwrap_or(Value::Null)
+        }
+        match self {
+            InputEvent::Press(b) => json!({ "press": value(b) }),
+            InputEvent::Release(b) => json!({ "release": value(b) }),
+            InputEvent::Cursor(pos) => json!({ "cursor": pos }),
+            InputEvent::Axis(args) => json!({ "axis": value(args) }),
+        }
+    }
+
+    pub fn from_value(v: &Value) -> Result<InputEvent, String> {
+        fn parse<T: serde::de::DeserializeOwned>(v: &Value) -> Result<T, String> {
+            serde_json::from_value(v.clone()).map_err(|e| e.to_string())
+        }
+        if !v["press"].is_null() {
+            Ok(InputEvent::Press(parse(&v["press"])?))
+        } else if !v["release"].is_null() {
+            Ok(InputEvent::Release(parse(&v["release"])?))
+        } else if !v["cursor"].is_null() {
+            Ok(InputEvent::Cursor(parse(&v["cursor"])?))
+        } else if !v["axis"].is_null() {
+            Ok(InputEvent::Axis(parse(&v["axis"])?))
+        } else {
+            Err(format!("invalid input event {}", v))
+        }
+    }
+}
+
+/// Control scheme where the heading follows the mouse cursor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MouseAim {
+    pub sensitivity: f64, // part of the angle to the cursor that is turned (1 aims right at it)
+    pub max_turn: f64,    // maximum rotation per frame (radians)
+}
+
+impl Default for MouseAim {
+    fn default() -> MouseAim {
+        MouseAim {
+            sensitivity: 1.0,
+            max_turn: PI / 30.0,
+        }
+    }
+}
+
+impl MouseAim {
+    pub fn from_value(v: &Value) -> MouseAim {
+        let default = MouseAim::default();
+        MouseAim {
+            sensitivity: v["sensitivity"].as_f64().unwrap_or(default.sensitivity),
+            max_turn: v["max_turn"]
+                .as_f64()
+                .map_or(default.max_turn, f64::to_radians),
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        json!({ "sensitivity": self.sensitivity, "max_turn": self.max_turn.to_degrees() })
+    }
+}
+
+/// Name of a unit variant as written by serde (`Space`, `Left`, ...)
+fn variant_name(v: &impl serde::Serialize) -> String {
+    match serde_json::to_value(v) {
+        Ok(Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+/// Translates keys, mouse and gamepad buttons into actions
+pub struct InputMap {
+    pub bindings: Vec<(Action, Binding)>, // an action can have several bindings
+    pub deadzone: f64,                    // axis positions below this are ignored
+    pub mouse_aim: Option<MouseAim>,      // turn towards the cursor instead of using keys only
+    pressed: Vec<Binding>,                // buttons currently held
+    axes: Vec<(u8, f64)>,                 // last position of each axis
+    rebinding: Option<Action>,            // the next input is bound to this action
+}
+
+impl InputMap {
+    fn with_keys(keys: &[(Action, Key)]) -> InputMap {
+        let mut bindings = keys
+            .iter()
+            .map(|&(a, k)| (a, Binding::Key(k)))
+            .collect::<Vec<_>>();
+        let others = [
+            (Action::MoveForward, Binding::Key(Key::Up)),
+            (Action::MoveBackward, Binding::Key(Key::Down)),
+            (Action::TurnLeft, Binding::Key(Key::Left)),
+            (Action::TurnRight, Binding::Key(Key::Right)),
+            (Action::Fire, Binding::Mouse(MouseButton::Left)),
+            (Action::Ability, Binding::Mouse(MouseButton::Right)),
+            (Action::MinimapZoom, Binding::Key(Key::M)),
+            (Action::Camera, Binding::Key(Key::C)),
+            // gamepad (SDL game controller layout)
+            (
+                Action::MoveForward,
+                Binding::Axis {
+                    axis: 1,
+                    positive: false,
+                },
+            ),
+            (
+                Action::MoveBackward,
+                Binding::Axis {
+                    axis: 1,
+                    positive: true,
+                },
+            ),
+            (
+                Action::StrafeLeft,
+                Binding::Axis {
+                    axis: 0,
+                    positive: false,
+                },
+            ),
+            (
+                Action::StrafeRight,
+                Binding::Axis {
+                    axis: 0,
+                    positive: true,
+                },
+            ),
+            (
+                Action::TurnLeft,
+                Binding::Axis {
+                    axis: 2,
+                    positive: false,
+                },
+            ),
+            (
+                Action::TurnRight,
+                Binding::Axis {
+                    axis: 2,
+                    positive: true,
+                },
+            ),
+            (
+                Action::Fire,
+                Binding::Axis {
+                    axis: 5,
+                    positive: true,
+                },
+            ),
+            (Action::Fire, Binding::Gamepad(10)),
+            (Action::Reload, Binding::Gamepad(2)),
+            (Action::Interact, Binding::Gamepad(0)),
+            (Action::Ability, Binding::Gamepad(3)),
+            (Action::MinimapZoom, Binding::Gamepad(4)),
+            (Action::Camera, Binding::Gamepad(5)),
+        ];
+        bindings.extend(others);
+
+        InputMap {
+            bindings,
+            deadzone: 0.2,
+            mouse_aim: None,
+            pressed: Vec::new(),
+            axes: Vec::new(),
+            rebinding: None,
+        }
+    }
+
+    /// ZQSD layout (the original controls), arrows turn
+    pub fn azerty() -> InputMap {
+        InputMap::with_keys(&[
+            (Action::MoveForward, Key::Z),
+            (Action::MoveBackward, Key::S),
+            (Action::StrafeLeft, Key::Q),
+            (Action::StrafeRight, Key::D),
+            (Action::Fire, Key::Space),
+            (Action::Reload, Key::R),
+            (Action::Interact, Key::E),
+            (Action::Ability, Key::LShift),
+        ])
+    }
+
+    /// WASD layout, arrows turn
+    pub fn qwerty() -> InputMap {
+        InputMap::with_keys(&[
+            (Action::MoveForward, Key::W),
+            (Action::MoveBackward, Key::S),
+            (Action::StrafeLeft, Key::A),
+            (Action::StrafeRight, Key::D),
+            (Action::Fire, Key::Space),
+            (Action::Reload, Key::R),
+            (Action::Interact, Key::E),
+            (Action::Ability, Key::LShift),
+        ])
+    }
+
+    pub fn preset(name: &str) -> Option<InputMap> {
+        match name {
+            "azerty" => Some(InputMap::azerty()),
+            "qwerty" => Some(InputMap::qwerty()),
+            _ => None,
+        }
+    }
+
+    /// Load a preset by name or a config file
+    pub fn load(name_or_path: &str) -> Result<InputMap, String> {
+        if let Some(map) = InputMap::preset(name_or_path) {
+            return Ok(map);
+        }
+        let data = std::fs::read_to_string(name_or_path)
+            .map_err(|e| format!("could not read {}: {}", name_or_path, e))?;
+        let v: Value = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+        InputMap::from_value(&v)
+    }
+
+    /// Config file: a preset, and the bindings that replace the ones of the preset
+    pub fn from_value(v: &Value) -> Result<InputMap, String> {
+        let preset = v["preset"].as_str().unwrap_or("azerty");
+        let mut map =
+            InputMap::preset(preset).ok_or_else(|| format!("unknown preset {}", preset))?;
+        if let Some(deadzone) = v["deadzone"].as_f64() {
+            map.deadzone = deadzone;
+        }
+        if !v["mouse_aim"].is_null() && v["mouse_aim"] != json!(false) {
+            map.mouse_aim = Some(MouseAim::from_value(&v["mouse_aim"]));
+        }
+
+        for (name, list) in v["bindings"].as_object().into_iter().flatten() {
+            let action =
+                Action::from_name(name).ok_or_else(|| format!("unknown action {}", name))?;
+            map.bindings.retain(|(a, _)| *a != action);
+            for b in list.as_array().into_iter().flatten() {
+                let b = b.as_str().ok_or_else(|| format!("invalid binding {}", b))?;
+                map.bindings.push((action, Binding::parse(b)?));
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut bindings = JsonMap::new();
+        for action in Action::ALL {
+            let list = self
+                .bindings_of(action)
+                .iter()
+                .map(|b| json!(b.name()))
+                .collect::<Vec<Value>>();
+            bindings.insert(action.name().to_string(), Value::Array(list));
+        }
+        let mouse_aim = self.mouse_aim.map_or(json!(false), |m| m.to_value());
+        json!({ "deadzone": self.deadzone, "mouse_aim": mouse_aim, "bindings": bindings })
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let data = serde_json::to_string_pretty(&self.to_value()).map_err(std::io::Error::other)?;
+        std::fs::write(path, data)
+    }
+
+    pub fn bindings_of(&self, action: Action) -> Vec<Binding> {
+        self.bindings
+            .iter()
+            .filter(|(a, _)| *a == action)
+            .map(|(_, b)| *b)
+            .collect()
+    }
+
+    /// Bind an input to an action, the input is removed from the other actions
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        self.bindings.retain(|(_, b)| *b != binding);
+        self.bindings.push((action, binding));
+    }
+
+    pub fn unbind(&mut self, action: Action) {
+        self.bindings.retain(|(a, _)| *a != action);
+    }
+
+    /// The next button pressed (or axis pushed) will be bound to the action
+    pub fn start_rebind(&mut self, action: Action) {
+        self.rebinding = Some(action);
+    }
+
+    pub fn cancel_rebind(&mut self) {
+        self.rebinding = None;
+    }
+
+    pub fn is_rebinding(&self) -> Option<Action> {
+        self.rebinding
+    }
+
+    /// Returns the actions started by the button (to trigger things only once per press)
+    pub fn press(&mut self, button: Button) -> Vec<Action> {
+        let binding = match Binding::from_button(button) {
+            Some(b) => b,
+            None => return Vec::new(),
+        };
+        if let Some(action) = self.rebinding.take() {
+            self.bind(action, binding);
+            return Vec::new();
+        }
+        if self.pressed.contains(&binding) {
+            return Vec::new(); // key repeat
+        }
+        self.pressed.push(binding);
+        self.bindings
+            .iter()
+            .filter(|(_, b)| *b == binding)
+            .map(|(a, _)| *a)
+            .collect()
+    }
+
+    pub fn release(&mut self, button: Button) {
+        if let Some(binding) = Binding::from_button(button) {
+            self.pressed.retain(|b| *b != binding);
+        }
+    }
+
+    pub fn move_axis(&mut self, args: ControllerAxisArgs) {
+        if let Some(action) = self.rebinding {
+            if f64::abs(args.position) > 0.5 {
+                let positive = args.position > 0.0;
+                self.bind(
+                    action,
+                    Binding::Axis {
+                        axis: args.axis,
+                        positive,
+                    },
+                );
+                self.rebinding = None;
+            }
+            return;
+        }
+        match self.axes.iter_mut().find(|(a, _)| *a == args.axis) {
+            Some((_, pos)) => *pos = args.position,
+            None => self.axes.push((args.axis, args.position)),
+        }
+    }
+
+    /// Forget everything that is held (when the window loses focus, ...)
+    pub fn clear(&mut self) {
+        self.pressed.clear();
+        self.axes.clear();
+    }
+
+    fn binding_value(&self, binding: &Binding) -> f64 {
+        match binding {
+            Binding::Axis { axis, positive } => {
+                let pos = self
+                    .axes
+                    .iter()
+                    .find(|(a, _)| a == axis)
+                    .map_or(0.0, |(_, p)| *p);
+                let pos = if *positive { pos } else { -pos };
+                if pos <= self.deadzone {
+                    0.0
+                } else {
+                    f64::min((pos - self.deadzone) / (1.0 - self.deadzone), 1.0)
+                }
+            }
+            b if self.pressed.contains(b) => 1.0,
+            _ => 0.0,
+        }
+    }
+
+    /// How much an action is wanted, between 0 and 1 (analog for axes)
+    pub fn value(&self, action: Action) -> f64 {
+        self.bindings
+            .iter()
+            .filter(|(a, _)| *a == action)
+            .map(|(_, b)| self.binding_value(b))
+            .fold(0.0, f64::max)
+    }
+
+    pub fn is_active(&self, action: Action) -> bool {
+        self.value(action) > 0.0
+    }
+}
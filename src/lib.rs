@@ -1,10 +1,27 @@
 #![allow(dead_code)]
 
+pub mod ai;
+pub mod bot;
+pub mod camera;
+pub mod cli;
+pub mod editor;
 pub mod enemy;
 pub mod game;
+pub mod hero;
+pub mod hud;
+pub mod input;
 pub mod map;
+pub mod minimap;
+pub mod nav;
 pub mod player;
+pub mod projectile;
 pub mod qtree;
+pub mod replay;
+pub mod sound;
+pub mod state;
+pub mod watch;
+pub mod wave;
+pub mod weapon;
 
 pub const WIDTH: u32 = 800;
 pub const HEIGHT: u32 = 600;
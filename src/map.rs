@@ -1,20 +1,41 @@
 use piston_window::*;
 
 use math_vector::Vector;
+use ray_tracing::Wall as RayTracingWall;
+use serde_json::{json, Value};
 
 pub mod element;
+pub mod generator;
+pub mod material;
+pub mod shape;
+pub mod spawn;
+pub mod tiled;
+pub mod validate;
 pub mod wall;
 
-use element::Element;
-use wall::Wall;
+use element::{Element, ElementKind};
+use material::Material;
+use shape::{Obstacle, Shape};
+use spawn::{Spawn, SpawnKind};
+use wall::{Wall, WallKind};
 
-use crate::{qtree::QTree, HEIGHT, WIDTH};
+use crate::{
+    qtree::{
+        bounds::{Point, Rect},
+        QTree,
+    },
+    HEIGHT, WIDTH,
+};
 
 pub struct Map {
-    pub elements: Vec<Element>, // map elements (textures, loots, ...)
-    pub walls: Vec<Wall>,       // all walls
-    pub sqtree: QTree,          // static quadtree
-    pub dqtree: QTree,          // dynamic quadtree
+    pub elements: Vec<Element>,             // pickups and triggers
+    pub walls: Vec<Wall>,                   // all walls
+    pub obstacles: Vec<Obstacle>,           // shapes (expanded into walls)
+    pub spawns: Vec<Spawn>,                 // player and enemy spawn points
+    pub materials: Vec<(String, Material)>, // palette (names and properties)
+    pub sqtree: QTree,                      // static quadtree
+    pub dqtree: QTree,                      // dynamic quadtree
+    pub dirty: bool,                        // walls changed since last update
 }
 
 impl Map {
@@ -24,8 +45,412 @@ impl Map {
         Map {
             elements: Vec::new(),
             walls: Vec::new(),
+            obstacles: Vec::new(),
+            spawns: Vec::new(),
+            materials: Material::defaults(),
             sqtree: QTree::new(center, 4, w, h),
             dqtree: QTree::new(center, 4, w, h),
+            dirty: true,
+        }
+    }
+
+    /// Load a map file (our own format, or a Tiled `.tmx` / `.json` map)
+    pub fn load(path: &str) -> Result<Map, String> {
+        let data =
+            std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+        let v: Value = if path.ends_with(".tmx") {
+            tiled::from_tmx(&data).map_err(|e| format!("{}: {}", path, e))?
+        } else {
+            let v = serde_json::from_str(&data).map_err(|e| format!("{}: {}", path, e))?;
+            if tiled::is_tiled(&v) {
+                tiled::from_json(&v).map_err(|e| format!("{}: {}", path, e))?
+            } else {
+                v
+            }
+        };
+        Map::from_value(&v).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Build a map from the content of a map file
+    pub fn from_value(v: &Value) -> Result<Map, String> {
+        let mut map = Map::new();
+
+        // materials are optional, they can override the default ones
+        let materials = v["materials"].as_array().cloned().unwrap_or_default();
+        for m in materials {
+            let name = m["name"].as_str().ok_or("a material needs a name")?;
+            let base = map.material(name).unwrap_or_else(Material::concrete);
+            match map.materials.iter().position(|(n, _)| n == name) {
+                Some(id) => map.materials[id].1 = Material::from_value(&m, id, &base)?,
+                None => {
+                    let id = map.materials.len();
+                    let material = Material::from_value(&m, id, &base)?;
+                    map.materials.push((name.to_string(), material));
+                }
+            }
+        }
+
+        let walls = v["walls"].as_array().cloned().unwrap_or_default();
+        for w in walls {
+            let p1 = parse_point(&w["p1"])?;
+            let p2 = parse_point(&w["p2"])?;
+            let wall = map.parse_wall(&w, p1, p2)?;
+            map.walls.push(wall);
+        }
+
+        // shapes are optional too
+        let shapes = v["shapes"].as_array().cloned().unwrap_or_default();
+        for s in shapes {
+            let number = |n: &Value| n.as_f64().ok_or(format!("invalid shape {}", s));
+            let shape = match s["type"].as_str().ok_or("a shape needs a type")? {
+                "polygon" => {
+                    let points = s["points"]
+                        .as_array()
+                        .ok_or("a polygon needs points")?
+                        .iter()
+                        .map(parse_point)
+                        .collect::<Result<Vec<Vector<f64>>, String>>()?;
+                    let closed = s["closed"].as_bool().unwrap_or(true);
+                    // fewer would make walls of length zero or the same wall twice
+                    let min = if closed { 3 } else { 2 };
+                    if points.len() < min {
+                        return Err(format!("a polygon needs at least {} points: {}", min, s));
+                    }
+                    Shape::Polygon { points, closed }
+                }
+                "rect" => {
+                    let (width, height) = (number(&s["size"][0])?, number(&s["size"][1])?);
+                    if width <= 0.0 || height <= 0.0 {
+                        return Err(format!("a rect needs a positive size: {}", s));
+                    }
+                    Shape::Rect {
+                        pos: parse_point(&s["pos"])?,
+                        width,
+                        height,
+                    }
+                }
+                "circle" => {
+                    let radius = number(&s["radius"])?;
+                    let segments = s["segments"].as_u64().unwrap_or(16) as usize;
+                    if radius <= 0.0 || segments < 3 {
+                        return Err(format!(
+                            "a circle needs a positive radius and at least 3 segments: {}",
+                            s
+                        ));
+                    }
+                    Shape::Circle {
+                        center: parse_point(&s["center"])?,
+                        radius,
+                        segments,
+                    }
+                }
+                t => return Err(format!("unknown shape type {}", t)),
+            };
+            let template = map.parse_wall(&s, Vector::default(), Vector::default())?;
+            map.add_obstacle(Obstacle::new(shape, template));
+        }
+
+        // elements are optional
+        let elements = v["elements"].as_array().cloned().unwrap_or_default();
+        for e in elements {
+            let name = e["kind"].as_str().ok_or("an element needs a kind")?;
+            let amount = e["amount"].as_f64().unwrap_or(0.0);
+            let trigger = e["trigger"].as_u64().map(|t| t as u32);
+            let kind = ElementKind::from_name(name, amount, e["weapon"].as_str(), trigger)
+                .ok_or_else(|| format!("invalid element {}", e))?;
+            let pos = parse_point(&e["pos"])?;
+            let respawn = e["respawn"].as_u64().unwrap_or(0);
+            map.elements.push(Element::new(kind, pos, respawn));
+        }
+
+        let spawns = v["spawns"].as_array().cloned().unwrap_or_default();
+        for sp in spawns {
+            let name = sp["kind"].as_str().unwrap_or("enemy");
+            let kind =
+                SpawnKind::from_name(name).ok_or_else(|| format!("unknown spawn kind {}", name))?;
+            map.spawns.push(Spawn::new(parse_point(&sp["pos"])?, kind));
+        }
+
+        Ok(map)
+    }
+
+    /// Convert the map back to the map file format (inverse of `from_value`)
+    pub fn to_value(&self) -> Value {
+        let defaults = Material::defaults();
+        let materials = self
+            .materials
+            .iter()
+            .filter(|m| !defaults.contains(m))
+            .map(|(name, m)| m.to_value(name))
+            .collect::<Vec<Value>>();
+
+        let walls = self
+            .walls
+            .iter()
+            .filter(|w| w.shape.is_none())
+            .map(|w| {
+                let mut v = json!({"p1": [w.p1.x, w.p1.y], "p2": [w.p2.x, w.p2.y]});
+                self.write_wall(w, &mut v);
+                v
+            })
+            .collect::<Vec<Value>>();
+
+        let shapes = self
+            .obstacles
+            .iter()
+            .map(|o| {
+                let mut v = match &o.shape {
+                    Shape::Polygon { points, closed } => json!({
+                        "type": "polygon",
+                        "points": points.iter().map(|p| [p.x, p.y]).collect::<Vec<_>>(),
+                        "closed": closed,
+                    }),
+                    Shape::Rect { pos, width, height } => json!({
+                        "type": "rect",
+                        "pos": [pos.x, pos.y],
+                        "size": [width, height],
+                    }),
+                    Shape::Circle {
+                        center,
+                        radius,
+                        segments,
+                    } => json!({
+                        "type": "circle",
+                        "center": [center.x, center.y],
+                        "radius": radius,
+                        "segments": segments,
+                    }),
+                };
+                self.write_wall(&o.template, &mut v);
+                v
+            })
+            .collect::<Vec<Value>>();
+
+        let spawns = self
+            .spawns
+            .iter()
+            .map(|s| json!({"pos": [s.pos.x, s.pos.y], "kind": s.kind.name()}))
+            .collect::<Vec<Value>>();
+
+        let elements = self
+            .elements
+            .iter()
+            .map(|e| {
+                let mut v = json!({
+                    "kind": e.kind.name(),
+                    "pos": [e.pos.x, e.pos.y],
+                    "respawn": e.respawn,
+                });
+                match &e.kind {
+                    ElementKind::Trigger(id) => v["trigger"] = json!(id),
+                    kind => v["amount"] = json!(kind.amount()),
+                }
+                if let ElementKind::Weapon { name, .. } = &e.kind {
+                    v["weapon"] = json!(name);
+                }
+                v
+            })
+            .collect::<Vec<Value>>();
+
+        json!({
+            "materials": materials,
+            "walls": walls,
+            "shapes": shapes,
+            "spawns": spawns,
+            "elements": elements,
+        })
+    }
+
+    /// Write the kind and material of a wall into its map file object
+    fn write_wall(&self, w: &Wall, v: &mut Value) {
+        match w.kind {
+            WallKind::Solid => (),
+            WallKind::Door { open, trigger } => {
+                v["kind"] = json!("door");
+                v["open"] = json!(open);
+                if let Some(t) = trigger {
+                    v["trigger"] = json!(t);
+                }
+            }
+            WallKind::Destructible { max_health, .. } => {
+                v["kind"] = json!("destructible");
+                v["health"] = json!(max_health);
+            }
+            WallKind::Glass => v["kind"] = json!("glass"),
+        }
+        if w.material != Wall::with_kind(w.p1, w.p2, w.kind).material {
+            v["material"] = json!(self.materials[w.material.id].0);
+        }
+    }
+
+    /// Save the map to a file (in the same format `Game::load_map` reads)
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let data = serde_json::to_string_pretty(&self.to_value()).unwrap();
+        std::fs::write(path, data)
+    }
+
+    /// Remove an obstacle and the walls it is made of
+    pub fn remove_obstacle(&mut self, id: usize) {
+        self.obstacles.remove(id);
+        self.walls.retain(|w| w.shape != Some(id));
+        for wall in self.walls.iter_mut() {
+            if let Some(s) = wall.shape {
+                if s > id {
+                    wall.shape = Some(s - 1);
+                }
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// All spawn points of a given kind
+    pub fn spawns_of(&self, kind: SpawnKind) -> Vec<Vector<f64>> {
+        self.spawns
+            .iter()
+            .filter(|s| s.kind == kind)
+            .map(|s| s.pos)
+            .collect()
+    }
+
+    /// Parse the kind and material of a wall (or of the walls of a shape)
+    fn parse_wall(&self, w: &Value, p1: Vector<f64>, p2: Vector<f64>) -> Result<Wall, String> {
+        let mut wall = Wall::with_kind(p1, p2, parse_kind(w)?);
+        if let Some(name) = w["material"].as_str() {
+            wall.material = self
+                .material(name)
+                .ok_or_else(|| format!("unknown material {}", name))?;
+        }
+        Ok(wall)
+    }
+
+    /// Add an obstacle and the walls it is made of
+    pub fn add_obstacle(&mut self, obstacle: Obstacle) {
+        let id = self.obstacles.len();
+        self.walls.append(&mut obstacle.to_walls(id));
+        self.obstacles.push(obstacle);
+        self.dirty = true;
+    }
+
+    /// Whether a point is inside one of the closed obstacles
+    pub fn is_inside_obstacle(&self, p: Vector<f64>) -> bool {
+        self.obstacles.iter().any(|o| o.shape.contains(p))
+    }
+
+    /// Whether a circle fits somewhere without overlapping a wall or an obstacle
+    pub fn is_free(&self, pos: Vector<f64>, radius: f64) -> bool {
+        !self.is_inside_obstacle(pos)
+            && self
+                .walls
+                .iter()
+                .all(|w| !w.blocks_movement() || w.distance_to(pos) >= radius)
+    }
+
+    /// Look up a material of the palette by name
+    pub fn material(&self, name: &str) -> Option<Material> {
+        self.materials
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, m)| *m)
+    }
+
+    /// Fraction of a sound emitted at `from` that can be heard at `to`
+    pub fn sound_attenuation(&self, from: Vector<f64>, to: Vector<f64>) -> f64 {
+        let rtwalls = self
+            .walls
+            .iter()
+            .filter(|w| w.blocks_movement())
+            .map(|w| w.to_rt())
+            .collect::<Vec<RayTracingWall>>();
+        ray_tracing::attenuation(from, to, &rtwalls)
+    }
+
+    /// Update the map state (respawn timers, static quadtree if geometry changed)
+    pub fn update(&mut self) {
+        for element in self.elements.iter_mut() {
+            element.update();
+        }
+        if self.dirty {
+            self.rebuild_sqtree();
+        }
+    }
+
+    /// Smallest rectangle around the walls (the size of the window around the origin without walls)
+    pub fn bounds(&self) -> Rect {
+        let mut points = self.walls.iter().flat_map(|w| [w.p1, w.p2]);
+        let first = match points.next() {
+            Some(p) => p,
+            None => {
+                return Rect {
+                    pos: Vector::default(),
+                    width: WIDTH as f64 / 2.0,
+                    height: HEIGHT as f64 / 2.0,
+                }
+            }
+        };
+        let (mut min, mut max) = (first, first);
+        for p in points {
+            min = Vector::new(min.x.min(p.x), min.y.min(p.y), 0.0);
+            max = Vector::new(max.x.max(p.x), max.y.max(p.y), 0.0);
+        }
+        Rect {
+            pos: (min + max) * 0.5,
+            width: (max.x - min.x) / 2.0,
+            height: (max.y - min.y) / 2.0,
+        }
+    }
+
+    /// Fill the static quadtree with the end and middle points of all blocking walls
+    pub fn rebuild_sqtree(&mut self) {
+        // sized to the map (walls outside of the tree would be left out)
+        let b = self.bounds();
+        self.sqtree = QTree::new(b.pos, 4, b.width + 1.0, b.height + 1.0);
+        for wall in self.walls.iter().filter(|w| w.blocks_movement()) {
+            let mid = (wall.p1 + wall.p2) * 0.5;
+            for pos in [wall.p1, wall.p2, mid] {
+                self.sqtree.insert(&Point {
+                    pos,
+                    data: Some(*wall),
+                });
+            }
+        }
+        self.dirty = false;
+    }
+
+    /// Open or close the closest door within reach of a position
+    pub fn interact(&mut self, pos: Vector<f64>, reach: f64) -> bool {
+        let door = self
+            .walls
+            .iter()
+            .enumerate()
+            .filter(|(_, w)| matches!(w.kind, WallKind::Door { .. }))
+            .map(|(i, w)| (i, w.distance_to(pos)))
+            .filter(|(_, d)| *d <= reach)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+
+        match door {
+            Some((i, _)) => {
+                self.walls[i].toggle();
+                self.dirty = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Open or close all doors linked to a trigger
+    pub fn trigger(&mut self, id: u32) {
+        for wall in self.walls.iter_mut() {
+            if matches!(wall.kind, WallKind::Door { trigger: Some(t), .. } if t == id) {
+                wall.toggle();
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// Damage a wall (only destructible walls are affected)
+    pub fn damage_wall(&mut self, index: usize, amount: f64) {
+        if self.walls[index].damage(amount) {
+            self.dirty = true;
         }
     }
 
@@ -40,5 +465,37 @@ impl Map {
         for wall in self.walls.iter_mut() {
             wall.draw(c, g, transform);
         }
+        for element in self.elements.iter_mut() {
+            element.draw(c, g, transform);
+        }
+    }
+}
+
+/// Parse the (optional) kind of a wall from the map file
+pub fn parse_kind(w: &Value) -> Result<WallKind, String> {
+    let kind = match w["kind"].as_str().unwrap_or("solid") {
+        "solid" => WallKind::Solid,
+        "door" => WallKind::Door {
+            open: w["open"].as_bool().unwrap_or(false),
+            trigger: w["trigger"].as_u64().map(|t| t as u32),
+        },
+        "destructible" => {
+            let health = w["health"].as_f64().unwrap_or(100.0);
+            WallKind::Destructible {
+                health,
+                max_health: health,
+            }
+        }
+        "glass" => WallKind::Glass,
+        k => return Err(format!("unknown wall kind {}", k)),
+    };
+    Ok(kind)
+}
+
+/// Parse a `[x, y]` array from the map file
+pub fn parse_point(v: &Value) -> Result<Vector<f64>, String> {
+    match (v[0].as_f64(), v[1].as_f64()) {
+        (Some(x), Some(y)) => Ok(Vector::new(x, y, 0.0)),
+        _ => Err(format!("invalid point {}", v)),
     }
 }
@@ -1,2 +1,125 @@
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub struct Element {}
+use math_vector::Vector;
+use piston_window::*;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ElementKind {
+    Ammo(u64),                          // adds ammo to the reserve
+    Health(f64),                        // heals the player
+    Armor(f64),                         // adds armor to the player
+    Weapon { name: String, ammo: u64 }, // gives a weapon (and some ammo)
+    Trigger(u32),                       // opens / closes the doors linked to it
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Element {
+    pub kind: ElementKind, // what the element gives when picked up
+    pub pos: Vector<f64>,  // position of element
+    pub radius: f64,       // pickup radius (and size for drawing)
+    pub respawn: u64,      // number of frames before respawning (0 means never)
+    pub cooldown: u64,     // frames left before the element is available again
+    pub picked: bool,      // whether the element has been picked up
+}
+
+impl ElementKind {
+    /// Parse the kind of an element from its name in the map file
+    pub fn from_name(
+        name: &str,
+        amount: f64,
+        weapon: Option<&str>,
+        trigger: Option<u32>,
+    ) -> Option<ElementKind> {
+        match name {
+            "ammo" => Some(ElementKind::Ammo(amount as u64)),
+            "health" => Some(ElementKind::Health(amount)),
+            "armor" => Some(ElementKind::Armor(amount)),
+            "trigger" => Some(ElementKind::Trigger(trigger?)),
+            "weapon" => Some(ElementKind::Weapon {
+                name: weapon?.to_string(),
+                ammo: amount as u64,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Name of the kind of element (as written in the map file)
+    pub fn name(&self) -> &str {
+        match self {
+            ElementKind::Ammo(_) => "ammo",
+            ElementKind::Health(_) => "health",
+            ElementKind::Armor(_) => "armor",
+            ElementKind::Weapon { .. } => "weapon",
+            ElementKind::Trigger(_) => "trigger",
+        }
+    }
+
+    /// Amount of ammo, health or armor given by the element
+    pub fn amount(&self) -> f64 {
+        match self {
+            ElementKind::Ammo(n) => *n as f64,
+            ElementKind::Health(h) => *h,
+            ElementKind::Armor(a) => *a,
+            ElementKind::Weapon { ammo, .. } => *ammo as f64,
+            ElementKind::Trigger(id) => *id as f64,
+        }
+    }
+
+    pub fn color(&self) -> [f32; 4] {
+        match self {
+            ElementKind::Ammo(_) => [0.9, 0.8, 0.2, 1.0],
+            ElementKind::Health(_) => [0.2, 0.9, 0.3, 1.0],
+            ElementKind::Armor(_) => [0.3, 0.5, 1.0, 1.0],
+            ElementKind::Weapon { .. } => [0.9, 0.4, 0.1, 1.0],
+            ElementKind::Trigger(_) => [0.6, 0.2, 0.8, 1.0],
+        }
+    }
+}
+
+impl Element {
+    pub fn new(kind: ElementKind, pos: Vector<f64>, respawn: u64) -> Element {
+        Element {
+            kind,
+            pos,
+            radius: 6.0,
+            respawn,
+            cooldown: 0,
+            picked: false,
+        }
+    }
+
+    /// Whether the element can currently be picked up
+    pub fn is_active(&self) -> bool {
+        !self.picked
+    }
+
+    /// Mark the element as picked up and start its respawn timer
+    pub fn pick(&mut self) {
+        self.picked = true;
+        self.cooldown = self.respawn;
+    }
+
+    /// Tick the respawn timer (elements with no respawn stay picked forever)
+    pub fn update(&mut self) {
+        if !self.picked || self.respawn == 0 {
+            return;
+        }
+        if self.cooldown > 0 {
+            self.cooldown -= 1;
+        }
+        if self.cooldown == 0 {
+            self.picked = false;
+        }
+    }
+
+    pub fn draw(&mut self, _c: &Context, g: &mut G2d, transform: math::Matrix2d) {
+        if !self.is_active() {
+            return;
+        }
+        let r = self.radius;
+        rectangle(
+            self.kind.color(),
+            [self.pos.x - r, self.pos.y - r, r * 2.0, r * 2.0],
+            transform,
+            g,
+        );
+    }
+}
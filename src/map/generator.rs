@@ -0,0 +1,379 @@
+use math_vector::Vector;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{
+    spawn::{Spawn, SpawnKind},
+    wall::Wall,
+    Map,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    Bsp,   // rooms linked by corridors (binary space partitioning)
+    Cave,  // organic outlines (cellular automaton)
+    Arena, // open area with scattered cover
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GeneratorParams {
+    pub layout: Layout,
+    pub seed: u64,
+    pub width: usize,    // number of cells
+    pub height: usize,   // number of cells
+    pub cell: f64,       // size of a cell in world units
+    pub min_room: usize, // minimum size of a room (bsp)
+    pub density: f64,    // initial fill rate (cave) or amount of cover (arena)
+    pub enemy_spawns: usize,
+}
+
+impl Default for GeneratorParams {
+    fn default() -> GeneratorParams {
+        GeneratorParams {
+            layout: Layout::Bsp,
+            seed: 0,
+            width: 20,
+            height: 15,
+            cell: 40.0,
+            min_room: 3,
+            density: 0.45,
+            enemy_spawns: 4,
+        }
+    }
+}
+
+impl Layout {
+    pub fn from_name(name: &str) -> Option<Layout> {
+        match name {
+            "bsp" => Some(Layout::Bsp),
+            "cave" => Some(Layout::Cave),
+            "arena" => Some(Layout::Arena),
+            _ => None,
+        }
+    }
+}
+
+impl GeneratorParams {
+    /// Parameters the layouts can not work with (too small a grid, density out of 0..1)
+    fn check(&self) -> Result<(), String> {
+        // the arena leaves a margin of two cells around the cover, up to 3 cells wide
+        let min = if self.layout == Layout::Arena { 8 } else { 3 };
+        if self.width < min || self.height < min {
+            return Err(format!(
+                "the map must be at least {}x{} cells, not {}x{}",
+                min, min, self.width, self.height
+            ));
+        }
+        // a density of 1 fills everything
+        if !(0.0..1.0).contains(&self.density) {
+            return Err(format!(
+                "the density must be at least 0 and below 1, not {}",
+                self.density
+            ));
+        }
+        if !(self.cell > 0.0 && self.cell.is_finite()) {
+            return Err(format!("invalid cell size {}", self.cell));
+        }
+        Ok(())
+    }
+}
+
+/// Occupancy grid used while generating (true means solid)
+struct Grid {
+    width: usize,
+    height: usize,
+    solid: Vec<bool>,
+}
+
+impl Grid {
+    fn new(width: usize, height: usize, solid: bool) -> Grid {
+        Grid {
+            width,
+            height,
+            solid: vec![solid; width * height],
+        }
+    }
+
+    /// Cells outside the grid are solid
+    fn get(&self, x: i64, y: i64) -> bool {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return true;
+        }
+        self.solid[y as usize * self.width + x as usize]
+    }
+
+    fn set(&mut self, x: usize, y: usize, solid: bool) {
+        self.solid[y * self.width + x] = solid;
+    }
+
+    fn carve_rect(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        for y in y0..y1 {
+            for x in x0..x1 {
+                self.set(x, y, false);
+            }
+        }
+    }
+
+    /// Carve an L-shaped corridor between two cells
+    fn carve_corridor(&mut self, a: (usize, usize), b: (usize, usize), rng: &mut StdRng) {
+        let corner = if rng.gen_bool(0.5) {
+            (b.0, a.1)
+        } else {
+            (a.0, b.1)
+        };
+        for (p, q) in [(a, corner), (corner, b)] {
+            for x in usize::min(p.0, q.0)..=usize::max(p.0, q.0) {
+                for y in usize::min(p.1, q.1)..=usize::max(p.1, q.1) {
+                    self.set(x, y, false);
+                }
+            }
+        }
+    }
+
+    /// Label the connected empty areas, returns the label of each cell
+    fn components(&self) -> Vec<Option<usize>> {
+        let mut labels = vec![None; self.solid.len()];
+        let mut count = 0;
+        for start in 0..self.solid.len() {
+            if self.solid[start] || labels[start].is_some() {
+                continue;
+            }
+            let mut stack = vec![start];
+            labels[start] = Some(count);
+            while let Some(i) = stack.pop() {
+                let (x, y) = ((i % self.width) as i64, (i / self.width) as i64);
+                for (nx, ny) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                    if self.get(nx, ny) {
+                        continue;
+                    }
+                    let n = ny as usize * self.width + nx as usize;
+                    if labels[n].is_none() {
+                        labels[n] = Some(count);
+                        stack.push(n);
+                    }
+                }
+            }
+            count += 1;
+        }
+        labels
+    }
+
+    /// Link every empty area to the largest one so that all empty cells are reachable
+    fn connect(&mut self, rng: &mut StdRng) {
+        loop {
+            let labels = self.components();
+            let count = labels.iter().flatten().max().map_or(0, |m| m + 1);
+            if count <= 1 {
+                return;
+            }
+            let mut sizes = vec![0; count];
+            for l in labels.iter().flatten() {
+                sizes[*l] += 1;
+            }
+            let main = (0..count).max_by_key(|&l| sizes[l]).unwrap();
+            let other = (0..count).find(|&l| l != main).unwrap();
+
+            // link a random cell of the smaller area to the closest cell of the main one
+            let cells = |l: usize| {
+                (0..labels.len())
+                    .filter(|&i| labels[i] == Some(l))
+                    .map(|i| (i % self.width, i / self.width))
+                    .collect::<Vec<_>>()
+            };
+            let (from, to) = (cells(other), cells(main));
+            let dist =
+                |a: &(usize, usize), b: &(usize, usize)| a.0.abs_diff(b.0) + a.1.abs_diff(b.1);
+            let a = from[rng.gen_range(0..from.len())];
+            let b = *to.iter().min_by_key(|b| dist(&a, b)).unwrap();
+            self.carve_corridor(a, b, rng);
+        }
+    }
+
+    fn empty_cells(&self) -> Vec<(usize, usize)> {
+        (0..self.solid.len())
+            .filter(|&i| !self.solid[i])
+            .map(|i| (i % self.width, i / self.width))
+            .collect()
+    }
+}
+
+/// Generate a map from a seed and parameters, all spawn points are reachable from each other
+pub fn generate(params: &GeneratorParams) -> Result<Map, String> {
+    params.check()?;
+    let mut rng = StdRng::seed_from_u64(params.seed);
+    let mut grid = match params.layout {
+        Layout::Bsp => bsp(params, &mut rng),
+        Layout::Cave => cave(params, &mut rng),
+        Layout::Arena => arena(params, &mut rng),
+    };
+    grid.connect(&mut rng);
+
+    let mut map = Map::new();
+    let (ox, oy) = (
+        -(params.width as f64) * params.cell / 2.0,
+        -(params.height as f64) * params.cell / 2.0,
+    );
+    let to_world = |x: f64, y: f64| Vector::new(ox + x * params.cell, oy + y * params.cell, 0.0);
+
+    for (x0, y0, x1, y1) in boundaries(&grid) {
+        map.walls.push(Wall::new(
+            to_world(x0 as f64, y0 as f64),
+            to_world(x1 as f64, y1 as f64),
+        ));
+    }
+
+    let mut cells = grid.empty_cells();
+    if cells.is_empty() {
+        return Err(format!(
+            "no room left for the player spawn (seed {}), try a lower density",
+            params.seed
+        ));
+    }
+    for i in 0..=params.enemy_spawns {
+        if cells.is_empty() {
+            break;
+        }
+        let (x, y) = cells.swap_remove(rng.gen_range(0..cells.len()));
+        let kind = if i == 0 {
+            SpawnKind::Player
+        } else {
+            SpawnKind::Enemy
+        };
+        let pos = to_world(x as f64 + 0.5, y as f64 + 0.5);
+        map.spawns.push(Spawn::new(pos, kind));
+    }
+    Ok(map)
+}
+
+fn bsp(params: &GeneratorParams, rng: &mut StdRng) -> Grid {
+    let mut grid = Grid::new(params.width, params.height, true);
+    let (w, h) = (params.width, params.height);
+    split(&mut grid, (1, 1, w - 1, h - 1), params.min_room, rng);
+    grid
+}
+
+/// Split a region in two (or carve a room in it), returns the center of a room inside it
+fn split(
+    grid: &mut Grid,
+    (x0, y0, x1, y1): (usize, usize, usize, usize),
+    min: usize,
+    rng: &mut StdRng,
+) -> (usize, usize) {
+    let (w, h) = (x1 - x0, y1 - y0);
+    let can_split_x = w >= 2 * (min + 1);
+    let can_split_y = h >= 2 * (min + 1);
+
+    if !can_split_x && !can_split_y {
+        // leaf: carve a room leaving a margin of one cell
+        let rw = rng.gen_range(usize::min(min, w - 1)..w);
+        let rh = rng.gen_range(usize::min(min, h - 1)..h);
+        let rx = x0 + rng.gen_range(0..=w - rw - 1);
+        let ry = y0 + rng.gen_range(0..=h - rh - 1);
+        grid.carve_rect(rx, ry, rx + rw, ry + rh);
+        return (rx + rw / 2, ry + rh / 2);
+    }
+
+    let vertical = if can_split_x && can_split_y {
+        w > h || (w == h && rng.gen_bool(0.5))
+    } else {
+        can_split_x
+    };
+    let (a, b) = if vertical {
+        let x = rng.gen_range(x0 + min + 1..=x1 - min - 1);
+        ((x0, y0, x, y1), (x, y0, x1, y1))
+    } else {
+        let y = rng.gen_range(y0 + min + 1..=y1 - min - 1);
+        ((x0, y0, x1, y), (x0, y, x1, y1))
+    };
+    let ca = split(grid, a, min, rng);
+    let cb = split(grid, b, min, rng);
+    grid.carve_corridor(ca, cb, rng);
+    if rng.gen_bool(0.5) {
+        ca
+    } else {
+        cb
+    }
+}
+
+fn cave(params: &GeneratorParams, rng: &mut StdRng) -> Grid {
+    let (w, h) = (params.width, params.height);
+    let mut grid = Grid::new(w, h, true);
+    for y in 1..h - 1 {
+        for x in 1..w - 1 {
+            grid.set(x, y, rng.gen_bool(params.density));
+        }
+    }
+
+    for _ in 0..5 {
+        let mut next = Grid::new(w, h, true);
+        for y in 1..h - 1 {
+            for x in 1..w - 1 {
+                let (xi, yi) = (x as i64, y as i64);
+                let walls = (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                    .filter(|&(dx, dy)| (dx, dy) != (0, 0) && grid.get(xi + dx, yi + dy))
+                    .count();
+                next.set(x, y, walls >= 5 || (walls >= 4 && grid.get(xi, yi)));
+            }
+        }
+        grid = next;
+    }
+    grid
+}
+
+fn arena(params: &GeneratorParams, rng: &mut StdRng) -> Grid {
+    let (w, h) = (params.width, params.height);
+    let mut grid = Grid::new(w, h, true);
+    grid.carve_rect(1, 1, w - 1, h - 1);
+
+    let count = ((w * h) as f64 * params.density / 20.0) as usize;
+    for _ in 0..count {
+        let (cw, ch) = (rng.gen_range(1..=3), rng.gen_range(1..=3));
+        let x = rng.gen_range(2..w - 2 - cw);
+        let y = rng.gen_range(2..h - 2 - ch);
+        for yy in y..y + ch {
+            for xx in x..x + cw {
+                grid.set(xx, yy, true);
+            }
+        }
+    }
+    grid
+}
+
+/// Segments between solid and empty cells, merged along rows and columns
+fn boundaries(grid: &Grid) -> Vec<(usize, usize, usize, usize)> {
+    let (w, h) = (grid.width as i64, grid.height as i64);
+    let mut segments = Vec::new();
+
+    // horizontal edges (between rows y - 1 and y)
+    for y in 0..=h {
+        let mut start = None;
+        for x in 0..=w {
+            let edge = x < w && grid.get(x, y - 1) != grid.get(x, y);
+            match (edge, start) {
+                (true, None) => start = Some(x),
+                (false, Some(s)) => {
+                    segments.push((s as usize, y as usize, x as usize, y as usize));
+                    start = None;
+                }
+                _ => (),
+            }
+        }
+    }
+
+    // vertical edges (between columns x - 1 and x)
+    for x in 0..=w {
+        let mut start = None;
+        for y in 0..=h {
+            let edge = y < h && grid.get(x - 1, y) != grid.get(x, y);
+            match (edge, start) {
+                (true, None) => start = Some(y),
+                (false, Some(s)) => {
+                    segments.push((x as usize, s as usize, x as usize, y as usize));
+                    start = None;
+                }
+                _ => (),
+            }
+        }
+    }
+    segments
+}
@@ -0,0 +1,134 @@
+use serde_json::{json, Value};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Texture {
+    Solid,  // plain line
+    Dashed, // long dashes
+    Dotted, // small dots
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Material {
+    pub id: usize,          // index of the material in the map palette
+    pub opaque: bool,       // whether the material blocks sight
+    pub penetration: f64,   // fraction of bullet damage kept when going through (0 stops bullets)
+    pub sound_damping: f64, // fraction of sound absorbed when going through (1 blocks sound)
+    pub color: [f32; 4],    // color of the wall
+    pub texture: Texture,   // how the wall is drawn
+    pub width: f64,         // line width of the wall
+}
+
+impl Material {
+    pub fn concrete() -> Material {
+        Material {
+            id: 0,
+            opaque: true,
+            penetration: 0.0,
+            sound_damping: 0.9,
+            color: [0.0, 0.0, 0.0, 1.0],
+            texture: Texture::Solid,
+            width: 1.0,
+        }
+    }
+
+    pub fn glass() -> Material {
+        Material {
+            id: 1,
+            opaque: false,
+            penetration: 0.8,
+            sound_damping: 0.5,
+            color: [0.6, 0.8, 1.0, 0.6],
+            texture: Texture::Solid,
+            width: 1.0,
+        }
+    }
+
+    pub fn wood() -> Material {
+        Material {
+            id: 2,
+            opaque: true,
+            penetration: 0.5,
+            sound_damping: 0.4,
+            color: [0.5, 0.3, 0.1, 1.0],
+            texture: Texture::Dashed,
+            width: 2.0,
+        }
+    }
+
+    /// Low walls: you can see and shoot over them (with some damage loss)
+    pub fn cover() -> Material {
+        Material {
+            id: 3,
+            opaque: false,
+            penetration: 0.3,
+            sound_damping: 0.1,
+            color: [0.35, 0.35, 0.35, 1.0],
+            texture: Texture::Dotted,
+            width: 3.0,
+        }
+    }
+
+    /// Materials every map knows about (ids are their position in the list)
+    pub fn defaults() -> Vec<(String, Material)> {
+        vec![
+            ("concrete".to_string(), Material::concrete()),
+            ("glass".to_string(), Material::glass()),
+            ("wood".to_string(), Material::wood()),
+            ("cover".to_string(), Material::cover()),
+        ]
+    }
+
+    /// Parse a material from the map file, missing fields are taken from `base`
+    pub fn from_value(v: &Value, id: usize, base: &Material) -> Result<Material, String> {
+        let color = match v["color"].as_array() {
+            Some(c) => {
+                let channel = |i: usize| {
+                    c.get(i)
+                        .and_then(|x| x.as_f64())
+                        .ok_or_else(|| format!("invalid color {}", v["color"]))
+                };
+                [
+                    channel(0)? as f32,
+                    channel(1)? as f32,
+                    channel(2)? as f32,
+                    c.get(3).and_then(|a| a.as_f64()).unwrap_or(1.0) as f32,
+                ]
+            }
+            None => base.color,
+        };
+        let texture = match v["texture"].as_str() {
+            Some("solid") => Texture::Solid,
+            Some("dashed") => Texture::Dashed,
+            Some("dotted") => Texture::Dotted,
+            Some(t) => return Err(format!("unknown texture {}", t)),
+            None => base.texture,
+        };
+        Ok(Material {
+            id,
+            opaque: v["opaque"].as_bool().unwrap_or(base.opaque),
+            penetration: v["penetration"].as_f64().unwrap_or(base.penetration),
+            sound_damping: v["sound_damping"].as_f64().unwrap_or(base.sound_damping),
+            color,
+            texture,
+            width: v["width"].as_f64().unwrap_or(base.width),
+        })
+    }
+
+    /// Inverse of `from_value`
+    pub fn to_value(&self, name: &str) -> Value {
+        let texture = match self.texture {
+            Texture::Solid => "solid",
+            Texture::Dashed => "dashed",
+            Texture::Dotted => "dotted",
+        };
+        json!({
+            "name": name,
+            "opaque": self.opaque,
+            "penetration": self.penetration,
+            "sound_damping": self.sound_damping,
+            "color": self.color,
+            "texture": texture,
+            "width": self.width,
+        })
+    }
+}
@@ -0,0 +1,118 @@
+use math_vector::Vector;
+
+use super::wall::Wall;
+use crate::PI;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    Polygon {
+        points: Vec<Vector<f64>>, // vertices in order
+        closed: bool,             // whether the last vertex is linked to the first one
+    },
+    Rect {
+        pos: Vector<f64>, // top left corner
+        width: f64,
+        height: f64,
+    },
+    Circle {
+        center: Vector<f64>,
+        radius: f64,
+        segments: usize, // number of walls used to approximate the circle
+    },
+}
+
+/// A shape made of walls that all share the same kind and material
+#[derive(Clone, Debug, PartialEq)]
+pub struct Obstacle {
+    pub shape: Shape,
+    pub template: Wall, // kind and material of the walls (its points are ignored)
+}
+
+impl Shape {
+    /// Vertices of the shape (circles are approximated by a regular polygon)
+    pub fn points(&self) -> Vec<Vector<f64>> {
+        match self {
+            Shape::Polygon { points, .. } => points.clone(),
+            Shape::Rect { pos, width, height } => vec![
+                *pos,
+                Vector::new(pos.x + width, pos.y, 0.0),
+                Vector::new(pos.x + width, pos.y + height, 0.0),
+                Vector::new(pos.x, pos.y + height, 0.0),
+            ],
+            Shape::Circle {
+                center,
+                radius,
+                segments,
+            } => (0..*segments)
+                .map(|i| {
+                    let a = 2.0 * PI * i as f64 / *segments as f64;
+                    Vector::new(
+                        center.x + radius * a.cos(),
+                        center.y + radius * a.sin(),
+                        0.0,
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        match self {
+            Shape::Polygon { closed, .. } => *closed,
+            _ => true,
+        }
+    }
+
+    /// Whether a point is inside the shape (always false for open polylines)
+    pub fn contains(&self, p: Vector<f64>) -> bool {
+        match self {
+            Shape::Circle { center, radius, .. } => {
+                (p - *center).length_squared() <= radius * radius
+            }
+            _ if !self.is_closed() => false,
+            _ => {
+                // even-odd rule
+                let pts = self.points();
+                if pts.len() < 3 {
+                    return false;
+                }
+                let mut inside = false;
+                let mut j = pts.len() - 1;
+                for i in 0..pts.len() {
+                    let (a, b) = (pts[i], pts[j]);
+                    if (a.y > p.y) != (b.y > p.y)
+                        && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x
+                    {
+                        inside = !inside;
+                    }
+                    j = i;
+                }
+                inside
+            }
+        }
+    }
+}
+
+impl Obstacle {
+    pub fn new(shape: Shape, template: Wall) -> Obstacle {
+        Obstacle { shape, template }
+    }
+
+    /// Expand the obstacle into the walls used for ray casting and collisions
+    pub fn to_walls(&self, id: usize) -> Vec<Wall> {
+        let pts = self.shape.points();
+        let n = if self.shape.is_closed() {
+            pts.len()
+        } else {
+            pts.len().saturating_sub(1)
+        };
+        (0..n)
+            .map(|i| Wall {
+                p1: pts[i],
+                p2: pts[(i + 1) % pts.len()],
+                shape: Some(id),
+                ..self.template
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,60 @@
+use math_vector::Vector;
+use piston_window::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpawnKind {
+    Player, // where the player (and its team) starts
+    Enemy,  // where the enemies appear
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Spawn {
+    pub pos: Vector<f64>,
+    pub kind: SpawnKind,
+}
+
+impl SpawnKind {
+    pub fn from_name(name: &str) -> Option<SpawnKind> {
+        match name {
+            "player" => Some(SpawnKind::Player),
+            "enemy" => Some(SpawnKind::Enemy),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            SpawnKind::Player => "player",
+            SpawnKind::Enemy => "enemy",
+        }
+    }
+}
+
+impl Spawn {
+    pub fn new(pos: Vector<f64>, kind: SpawnKind) -> Spawn {
+        Spawn { pos, kind }
+    }
+
+    /// Spawns are only drawn for debugging (and in the editor)
+    pub fn draw(&self, _c: &Context, g: &mut G2d, transform: math::Matrix2d) {
+        let color = match self.kind {
+            SpawnKind::Player => [0.0, 1.0, 0.0, 0.5],
+            SpawnKind::Enemy => [1.0, 0.0, 0.0, 0.5],
+        };
+        let (x, y) = (self.pos.x, self.pos.y);
+        line(
+            color,
+            1.0,
+            [x - 5.0, y - 5.0, x + 5.0, y + 5.0],
+            transform,
+            g,
+        );
+        line(
+            color,
+            1.0,
+            [x - 5.0, y + 5.0, x + 5.0, y - 5.0],
+            transform,
+            g,
+        );
+    }
+}
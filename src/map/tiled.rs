@@ -0,0 +1,267 @@
+use serde_json::{json, Map as JsonMap, Value};
+
+/// An object of an object layer, with the fields we care about
+#[derive(Debug, Default)]
+struct TiledObject {
+    kind: String, // type (or class) of the object
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    rotation: f64, // degrees, clockwise around (x, y)
+    polygon: Option<Vec<(f64, f64)>>,
+    polyline: Option<Vec<(f64, f64)>>,
+    ellipse: bool,
+    point: bool,
+    properties: JsonMap<String, Value>,
+}
+
+/// Whether a parsed JSON file was exported by Tiled (rather than being one of our maps)
+pub fn is_tiled(v: &Value) -> bool {
+    v.get("tiledversion").is_some() || v["layers"].is_array()
+}
+
+/// Convert a Tiled JSON map into our map format (only the object layers are read)
+pub fn from_json(v: &Value) -> Result<Value, String> {
+    let mut objects = Vec::new();
+    read_json_layers(&v["layers"], &mut objects)?;
+    Ok(to_value(&json_properties(&v["properties"]), &objects))
+}
+
+/// Convert a Tiled TMX (XML) map into our map format (only the object layers are read)
+pub fn from_tmx(data: &str) -> Result<Value, String> {
+    let doc = roxmltree::Document::parse(data).map_err(|e| e.to_string())?;
+    let root = doc.root_element();
+
+    let properties = root
+        .children()
+        .find(|n| n.has_tag_name("properties"))
+        .map(tmx_properties)
+        .unwrap_or_default();
+
+    let mut objects = Vec::new();
+    read_tmx_layers(root, &mut objects)?;
+    Ok(to_value(&properties, &objects))
+}
+
+/// Objects of the object layers of a TMX map or group (and of the groups inside)
+fn read_tmx_layers(parent: roxmltree::Node, objects: &mut Vec<TiledObject>) -> Result<(), String> {
+    for layer in parent.children() {
+        match layer.tag_name().name() {
+            "group" => read_tmx_layers(layer, objects)?,
+            "objectgroup" => {
+                for node in layer.children().filter(|n| n.has_tag_name("object")) {
+                    objects.push(read_tmx_object(node)?);
+                }
+            }
+            _ => (), // tile and image layers are not supported (nor the tilesets)
+        }
+    }
+    Ok(())
+}
+
+/// One object of a TMX object layer
+fn read_tmx_object(node: roxmltree::Node) -> Result<TiledObject, String> {
+    let attr = |name: &str| node.attribute(name).and_then(|a| a.parse::<f64>().ok());
+    let child = |name: &str| node.children().find(|n| n.has_tag_name(name));
+    let points = |name: &str| {
+        child(name)
+            .and_then(|n| n.attribute("points"))
+            .map(|pts| parse_tmx_points(pts, node.attribute("id").unwrap_or("")))
+            .transpose()
+    };
+    Ok(TiledObject {
+        kind: node
+            .attribute("type")
+            .or(node.attribute("class"))
+            .unwrap_or("")
+            .to_string(),
+        x: attr("x").unwrap_or(0.0),
+        y: attr("y").unwrap_or(0.0),
+        width: attr("width").unwrap_or(0.0),
+        height: attr("height").unwrap_or(0.0),
+        rotation: attr("rotation").unwrap_or(0.0),
+        polygon: points("polygon")?,
+        polyline: points("polyline")?,
+        ellipse: child("ellipse").is_some(),
+        point: child("point").is_some() || node.attribute("gid").is_some(),
+        properties: child("properties").map(tmx_properties).unwrap_or_default(),
+    })
+}
+
+/// Objects of the object layers of a Tiled JSON map or group (and of the groups inside)
+fn read_json_layers(layers: &Value, objects: &mut Vec<TiledObject>) -> Result<(), String> {
+    for layer in layers.as_array().cloned().unwrap_or_default() {
+        match layer["type"].as_str() {
+            Some("group") => read_json_layers(&layer["layers"], objects)?,
+            Some("objectgroup") => {
+                for o in layer["objects"].as_array().cloned().unwrap_or_default() {
+                    let points = |name: &str| {
+                        let invalid = || format!("invalid {} of object {}", name, o["id"]);
+                        o[name]
+                            .as_array()
+                            .map(|pts| {
+                                pts.iter()
+                                    .map(|p| {
+                                        let x = p["x"].as_f64().ok_or_else(invalid)?;
+                                        let y = p["y"].as_f64().ok_or_else(invalid)?;
+                                        Ok((x, y))
+                                    })
+                                    .collect::<Result<Vec<(f64, f64)>, String>>()
+                            })
+                            .transpose()
+                    };
+                    objects.push(TiledObject {
+                        kind: o["type"]
+                            .as_str()
+                            .or(o["class"].as_str())
+                            .unwrap_or("")
+                            .to_string(),
+                        x: o["x"].as_f64().unwrap_or(0.0),
+                        y: o["y"].as_f64().unwrap_or(0.0),
+                        width: o["width"].as_f64().unwrap_or(0.0),
+                        height: o["height"].as_f64().unwrap_or(0.0),
+                        rotation: o["rotation"].as_f64().unwrap_or(0.0),
+                        polygon: points("polygon")?,
+                        polyline: points("polyline")?,
+                        ellipse: o["ellipse"].as_bool().unwrap_or(false),
+                        point: o["point"].as_bool().unwrap_or(false) || o.get("gid").is_some(),
+                        properties: json_properties(&o["properties"]),
+                    });
+                }
+            }
+            _ => (), // tile and image layers are not supported
+        }
+    }
+    Ok(())
+}
+
+/// Custom properties of a Tiled JSON object or map (`[{"name": .., "value": ..}, ..]`)
+fn json_properties(v: &Value) -> JsonMap<String, Value> {
+    let mut properties = JsonMap::new();
+    for p in v.as_array().cloned().unwrap_or_default() {
+        if let Some(name) = p["name"].as_str() {
+            properties.insert(name.to_string(), p["value"].clone());
+        }
+    }
+    properties
+}
+
+/// Custom properties of a TMX object or map (`<property name=".." value=".."/>`)
+fn tmx_properties(node: roxmltree::Node) -> JsonMap<String, Value> {
+    let mut properties = JsonMap::new();
+    for p in node.children().filter(|n| n.has_tag_name("property")) {
+        let name = match p.attribute("name") {
+            Some(name) => name,
+            None => continue,
+        };
+        let raw = p.attribute("value").or(p.text()).unwrap_or("");
+        let value = match p.attribute("type") {
+            Some("int") | Some("float") => raw.parse::<f64>().map_or(Value::Null, |f| json!(f)),
+            Some("bool") => Value::Bool(raw == "true"),
+            _ => Value::String(raw.to_string()),
+        };
+        properties.insert(name.to_string(), value);
+    }
+    properties
+}
+
+/// Parse the `points` attribute of a TMX polygon (`x1,y1 x2,y2 ...`) of an object
+fn parse_tmx_points(points: &str, object: &str) -> Result<Vec<(f64, f64)>, String> {
+    points
+        .split_whitespace()
+        .map(|p| {
+            let (x, y) = p.split_once(',').unwrap_or((p, ""));
+            match (x.parse(), y.parse()) {
+                (Ok(x), Ok(y)) => Ok((x, y)),
+                _ => Err(format!("invalid point {} of object {}", p, object)),
+            }
+        })
+        .collect()
+}
+
+/// Build a map in our own format from the objects of the Tiled map: polylines are walls,
+/// polygons, rectangles and ellipses are shapes and typed points are spawns or elements
+fn to_value(properties: &JsonMap<String, Value>, objects: &[TiledObject]) -> Value {
+    // map properties named `material.<name>` describe a material
+    let mut materials = Vec::new();
+    for (name, value) in properties {
+        if let Some(material) = name.strip_prefix("material.") {
+            let mut m = match value {
+                Value::String(s) => serde_json::from_str(s).unwrap_or_else(|_| json!({})),
+                v => v.clone(),
+            };
+            m["name"] = json!(material);
+            materials.push(m);
+        }
+    }
+
+    let (mut walls, mut shapes, mut spawns, mut elements) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for o in objects {
+        // custom properties are copied as is (`kind`, `material`, `amount`, `respawn`, ...)
+        let mut v = Value::Object(o.properties.clone());
+        // map position of a point of the object (relative to its origin, before the rotation)
+        let (sin, cos) = o.rotation.to_radians().sin_cos();
+        let place = |x: f64, y: f64| json!([o.x + x * cos - y * sin, o.y + x * sin + y * cos]);
+        let absolute = |pts: &[(f64, f64)]| {
+            pts.iter()
+                .map(|&(x, y)| place(x, y))
+                .collect::<Vec<Value>>()
+        };
+
+        if let Some(pts) = &o.polyline {
+            let pts = absolute(pts);
+            if pts.len() == 2 {
+                v["p1"] = pts[0].clone();
+                v["p2"] = pts[1].clone();
+                walls.push(v);
+            } else {
+                v["type"] = json!("polygon");
+                v["points"] = json!(pts);
+                v["closed"] = json!(false);
+                shapes.push(v);
+            }
+        } else if let Some(pts) = &o.polygon {
+            v["type"] = json!("polygon");
+            v["points"] = json!(absolute(pts));
+            shapes.push(v);
+        } else if o.point || (o.width == 0.0 && o.height == 0.0) {
+            let pos = json!([o.x, o.y]);
+            match o.kind.as_str() {
+                "spawn" | "player_spawn" => spawns.push(json!({"pos": pos, "kind": "player"})),
+                "enemy_spawn" => spawns.push(json!({"pos": pos, "kind": "enemy"})),
+                "" => (),
+                kind => {
+                    v["kind"] = json!(kind);
+                    v["pos"] = pos;
+                    elements.push(v);
+                }
+            }
+        } else if o.ellipse {
+            v["type"] = json!("circle");
+            v["center"] = place(o.width / 2.0, o.height / 2.0);
+            v["radius"] = json!((o.width + o.height) / 4.0);
+            shapes.push(v);
+        } else if o.rotation != 0.0 {
+            // a rotated rectangle is a polygon
+            let (w, h) = (o.width, o.height);
+            v["type"] = json!("polygon");
+            v["points"] = json!(absolute(&[(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)]));
+            shapes.push(v);
+        } else {
+            v["type"] = json!("rect");
+            v["pos"] = json!([o.x, o.y]);
+            v["size"] = json!([o.width, o.height]);
+            shapes.push(v);
+        }
+    }
+
+    json!({
+        "materials": materials,
+        "walls": walls,
+        "shapes": shapes,
+        "spawns": spawns,
+        "elements": elements,
+    })
+}
@@ -0,0 +1,231 @@
+use std::fmt;
+
+use math_vector::Vector;
+
+use super::{spawn::SpawnKind, Map};
+use crate::{nav::NavGrid, qtree::bounds::Point};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IssueKind {
+    ZeroLengthWall,   // both end points are the same
+    DuplicateWall,    // same segment twice (in any direction)
+    OutOfBounds,      // spawn or element outside of the walls
+    BlockedSpawn,     // spawn point inside a wall or an obstacle
+    UnreachableSpawn, // spawn point that cannot be reached from the first player spawn
+    Gap,              // wall ends close to another wall without touching it
+}
+
+#[derive(Clone, Debug)]
+pub struct Issue {
+    pub kind: IssueKind,
+    pub pos: Vector<f64>, // where the issue is
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ValidateOptions {
+    pub epsilon: f64,      // distance under which two points are the same
+    pub gap: f64,          // distance under which two walls should probably touch
+    pub agent_radius: f64, // radius of the players and enemies
+    pub cell: f64,         // size of the cells used to check reachability
+}
+
+impl Default for ValidateOptions {
+    fn default() -> ValidateOptions {
+        ValidateOptions {
+            epsilon: 1e-6,
+            gap: 20.0,
+            agent_radius: 10.0,
+            cell: 10.0,
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} at ({:.1}, {:.1}): {}",
+            self.kind, self.pos.x, self.pos.y, self.message
+        )
+    }
+}
+
+impl Issue {
+    fn new(kind: IssueKind, pos: Vector<f64>, message: String) -> Issue {
+        Issue { kind, pos, message }
+    }
+}
+
+/// Check a map with the default options
+pub fn validate(map: &Map) -> Vec<Issue> {
+    validate_with(map, &ValidateOptions::default())
+}
+
+/// Report everything that looks broken in a map
+pub fn validate_with(map: &Map, opts: &ValidateOptions) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let eps = opts.epsilon;
+    let same = |a: Vector<f64>, b: Vector<f64>| a.distance(b) <= eps;
+
+    for (i, w) in map.walls.iter().enumerate() {
+        if same(w.p1, w.p2) {
+            let msg = format!("wall {} has a length of zero", i);
+            issues.push(Issue::new(IssueKind::ZeroLengthWall, w.p1, msg));
+        }
+        for (j, o) in map.walls.iter().enumerate().skip(i + 1) {
+            if (same(w.p1, o.p1) && same(w.p2, o.p2)) || (same(w.p1, o.p2) && same(w.p2, o.p1)) {
+                let msg = format!("walls {} and {} are the same", i, j);
+                issues.push(Issue::new(IssueKind::DuplicateWall, w.p1, msg));
+            }
+        }
+    }
+
+    // everything should be within the walls
+    let boundary = map.bounds();
+    let outside = |pos: Vector<f64>| !boundary.contains(&Point { pos, data: None });
+    for (i, s) in map.spawns.iter().enumerate() {
+        if outside(s.pos) {
+            let msg = format!("spawn {} is outside of the map bounds", i);
+            issues.push(Issue::new(IssueKind::OutOfBounds, s.pos, msg));
+        }
+    }
+    for (i, e) in map.elements.iter().enumerate() {
+        if outside(e.pos) {
+            let msg = format!(
+                "element {} ({}) is outside of the map bounds",
+                i,
+                e.kind.name()
+            );
+            issues.push(Issue::new(IssueKind::OutOfBounds, e.pos, msg));
+        }
+    }
+
+    // wall ends that almost touch another wall (a corridor that is not sealed)
+    for (i, w) in map.walls.iter().enumerate() {
+        for p in [w.p1, w.p2] {
+            let gap = map
+                .walls
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(j, o)| (j, o.distance_to(p)))
+                .filter(|(_, d)| *d > eps && *d < opts.gap)
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            let touches = map
+                .walls
+                .iter()
+                .enumerate()
+                .any(|(j, o)| j != i && o.distance_to(p) <= eps);
+            if let (Some((j, d)), false) = (gap, touches) {
+                let msg = format!("gap of {:.1} between walls {} and {}", d, i, j);
+                issues.push(Issue::new(IssueKind::Gap, p, msg));
+            }
+        }
+    }
+
+    // every spawn must be reachable from the first player spawn
+    if !map.spawns.is_empty() {
+        let grid = NavGrid::new(map, opts.cell, opts.agent_radius);
+        for (i, s) in map.spawns.iter().enumerate() {
+            if !grid.is_walkable(s.pos) {
+                let msg = format!("spawn {} ({}) is inside a wall", i, s.kind.name());
+                issues.push(Issue::new(IssueKind::BlockedSpawn, s.pos, msg));
+            }
+        }
+
+        let start = map
+            .spawns
+            .iter()
+            .find(|s| s.kind == SpawnKind::Player && grid.is_walkable(s.pos))
+            .or_else(|| map.spawns.iter().find(|s| grid.is_walkable(s.pos)));
+        if let Some(start) = start {
+            let reachable = grid.reachable(start.pos);
+            for (i, s) in map.spawns.iter().enumerate() {
+                let ok = grid
+                    .cell_of(s.pos)
+                    .is_some_and(|(x, y)| reachable[y * grid.width + x]);
+                if !ok && grid.is_walkable(s.pos) {
+                    let msg = format!("spawn {} ({}) cannot be reached", i, s.kind.name());
+                    issues.push(Issue::new(IssueKind::UnreachableSpawn, s.pos, msg));
+                }
+            }
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{spawn::Spawn, wall::Wall};
+
+    fn v(x: f64, y: f64) -> Vector<f64> {
+        Vector::new(x, y, 0.0)
+    }
+
+    /// Closed square room of 200 units around the origin with a player spawn in it
+    fn room() -> Map {
+        let mut map = Map::new();
+        let corners = [
+            v(-100.0, -100.0),
+            v(100.0, -100.0),
+            v(100.0, 100.0),
+            v(-100.0, 100.0),
+        ];
+        for i in 0..4 {
+            map.walls.push(Wall::new(corners[i], corners[(i + 1) % 4]));
+        }
+        map.spawns
+            .push(Spawn::new(v(-50.0, -50.0), SpawnKind::Player));
+        map
+    }
+
+    fn kinds(map: &Map) -> Vec<IssueKind> {
+        validate(map).iter().map(|i| i.kind).collect()
+    }
+
+    #[test]
+    fn closed_room_is_valid() {
+        let mut map = room();
+        map.spawns.push(Spawn::new(v(50.0, 50.0), SpawnKind::Enemy));
+        assert_eq!(kinds(&map), vec![]);
+    }
+
+    #[test]
+    fn broken_walls() {
+        let mut map = room();
+        map.walls.push(Wall::new(v(10.0, 10.0), v(10.0, 10.0)));
+        map.walls
+            .push(Wall::new(v(100.0, -100.0), v(-100.0, -100.0)));
+        let k = kinds(&map);
+        assert!(k.contains(&IssueKind::ZeroLengthWall));
+        assert!(k.contains(&IssueKind::DuplicateWall));
+    }
+
+    #[test]
+    fn gap_between_walls() {
+        let mut map = room();
+        map.walls.push(Wall::new(v(-100.0, 0.0), v(-10.0, 0.0)));
+        map.walls.push(Wall::new(v(0.0, 0.0), v(100.0, 0.0)));
+        assert!(kinds(&map).contains(&IssueKind::Gap));
+    }
+
+    #[test]
+    fn spawns_outside_blocked_or_unreachable() {
+        let mut map = room();
+        map.spawns.push(Spawn::new(v(300.0, 0.0), SpawnKind::Enemy));
+        map.spawns.push(Spawn::new(v(100.0, 0.0), SpawnKind::Enemy));
+        // a sealed room in the corner
+        map.walls.push(Wall::new(v(40.0, 100.0), v(40.0, 40.0)));
+        map.walls.push(Wall::new(v(40.0, 40.0), v(100.0, 40.0)));
+        map.spawns.push(Spawn::new(v(70.0, 70.0), SpawnKind::Enemy));
+
+        let issues = validate(&map);
+        let at = |kind: IssueKind| issues.iter().filter(|i| i.kind == kind).count();
+        assert_eq!(at(IssueKind::OutOfBounds), 1);
+        assert_eq!(at(IssueKind::BlockedSpawn), 1);
+        assert_eq!(at(IssueKind::UnreachableSpawn), 2);
+    }
+}
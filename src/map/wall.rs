@@ -1,15 +1,50 @@
 use math_vector::Vector;
 use piston_window::*;
+use ray_tracing::Wall as RayTracingWall;
+
+use super::material::{Material, Texture};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WallKind {
+    Solid,                                         // plain wall, never changes
+    Door { open: bool, trigger: Option<u32> },     // opens / closes on interaction or trigger
+    Destructible { health: f64, max_health: f64 }, // can be broken by bullets
+    Glass,                                         // blocks movement but not sight
+}
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Wall {
     pub p1: Vector<f64>,
     pub p2: Vector<f64>,
+    pub kind: WallKind,
+    pub material: Material,
+    pub shape: Option<usize>, // index of the obstacle the wall was expanded from
 }
 
 impl Wall {
     pub fn new(p1: Vector<f64>, p2: Vector<f64>) -> Wall {
-        Wall { p1, p2 }
+        Wall {
+            p1,
+            p2,
+            kind: WallKind::Solid,
+            material: Material::concrete(),
+            shape: None,
+        }
+    }
+
+    pub fn with_kind(p1: Vector<f64>, p2: Vector<f64>, kind: WallKind) -> Wall {
+        let material = match kind {
+            WallKind::Glass => Material::glass(),
+            WallKind::Door { .. } => Material::wood(),
+            _ => Material::concrete(),
+        };
+        Wall {
+            p1,
+            p2,
+            kind,
+            material,
+            shape: None,
+        }
     }
 
     pub fn get_p1(&mut self) -> Vector<f64> {
@@ -20,13 +55,115 @@ impl Wall {
         self.p2
     }
 
+    /// Whether the wall has been destroyed
+    pub fn is_broken(&self) -> bool {
+        matches!(self.kind, WallKind::Destructible { health, .. } if health <= 0.0)
+    }
+
+    /// Whether the wall is an open door
+    pub fn is_open(&self) -> bool {
+        matches!(self.kind, WallKind::Door { open: true, .. })
+    }
+
+    pub fn blocks_movement(&self) -> bool {
+        !self.is_broken() && !self.is_open()
+    }
+
+    pub fn blocks_sight(&self) -> bool {
+        self.blocks_movement() && self.kind != WallKind::Glass && self.material.opaque
+    }
+
+    /// Whether bullets are stopped by the wall (instead of going through it)
+    pub fn blocks_bullets(&self) -> bool {
+        self.blocks_movement() && self.material.penetration <= 0.0
+    }
+
+    /// Conversion to a wall for the ray-tracing queries
+    pub fn to_rt(&self) -> RayTracingWall {
+        RayTracingWall::with_material(
+            self.p1,
+            self.p2,
+            self.blocks_sight(),
+            self.material.penetration,
+            self.material.sound_damping,
+        )
+    }
+
+    /// Open or close a door, returns false if the wall is not a door
+    pub fn toggle(&mut self) -> bool {
+        match &mut self.kind {
+            WallKind::Door { open, .. } => {
+                *open = !*open;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Damage a destructible wall, returns true if the wall just broke
+    pub fn damage(&mut self, amount: f64) -> bool {
+        match &mut self.kind {
+            WallKind::Destructible { health, .. } if *health > 0.0 => {
+                *health -= amount;
+                *health <= 0.0
+            }
+            _ => false,
+        }
+    }
+
+    /// Shortest distance from a point to the wall segment
+    pub fn distance_to(&self, p: Vector<f64>) -> f64 {
+        let (dx, dy) = (self.p2.x - self.p1.x, self.p2.y - self.p1.y);
+        let l2 = dx * dx + dy * dy;
+        if l2 == 0.0 {
+            return self.p1.distance(p);
+        }
+        let t = ((p.x - self.p1.x) * dx + (p.y - self.p1.y) * dy) / l2;
+        let t = t.clamp(0.0, 1.0);
+        let proj = Vector::new(self.p1.x + t * dx, self.p1.y + t * dy, 0.0);
+        proj.distance(p)
+    }
+
     pub fn draw(&mut self, _c: &Context, g: &mut G2d, transform: math::Matrix2d) {
-        line(
-            [0.0, 0.0, 0.0, 1.0],
-            1.0,
-            [self.p1.x, self.p1.y, self.p2.x, self.p2.y],
-            transform,
-            g,
-        );
+        let mut color = self.material.color;
+        match self.kind {
+            WallKind::Door { open: true, .. } => color[3] *= 0.3,
+            WallKind::Destructible { health, max_health } => {
+                if health <= 0.0 {
+                    return; // nothing left to draw
+                }
+                color[3] *= 0.3 + 0.7 * (health / max_health) as f32;
+            }
+            _ => (),
+        }
+
+        let (on, off) = match self.material.texture {
+            Texture::Solid => {
+                let l = [self.p1.x, self.p1.y, self.p2.x, self.p2.y];
+                line(color, self.material.width, l, transform, g);
+                return;
+            }
+            Texture::Dashed => (6.0, 4.0),
+            Texture::Dotted => (1.0, 3.0),
+        };
+
+        let len = self.p1.distance(self.p2);
+        if len == 0.0 {
+            return;
+        }
+        let dir = (self.p2 - self.p1) * (1.0 / len);
+        let mut t = 0.0;
+        while t < len {
+            let a = self.p1 + dir * t;
+            let b = self.p1 + dir * f64::min(t + on, len);
+            line(
+                color,
+                self.material.width,
+                [a.x, a.y, b.x, b.y],
+                transform,
+                g,
+            );
+            t += on + off;
+        }
     }
 }
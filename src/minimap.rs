@@ -0,0 +1,235 @@
+use math_vector::Vector;
+use piston_window::*;
+
+use crate::{
+    map::Map,
+    player::Player,
+    sound::{SoundEvent, SoundKind},
+    PI,
+};
+
+/// Scales of the minimap (minimap pixels per world unit)
+pub const ZOOMS: [f64; 3] = [0.1, 0.2, 0.4];
+
+/// Sound heard recently, drawn as a fading circle
+#[derive(Clone, Copy, Debug)]
+pub struct Ping {
+    pub kind: SoundKind,
+    pub pos: Vector<f64>,
+    pub age: u64, // frames since it was heard
+}
+
+/// North-up map of what the player has explored, drawn in a corner of the screen
+pub struct Minimap {
+    pub visible: bool,
+    pub seen: Vec<bool>,  // walls seen so far (same order as the map walls)
+    pub zoom: usize,      // index in `ZOOMS`
+    pub size: f64,        // width and height on screen
+    pub pings: Vec<Ping>, // recent sounds
+    pub ping_time: u64,   // how long a ping stays (frames)
+    pub min_volume: f64,  // sounds quieter than this are not shown
+}
+
+impl Default for Minimap {
+    fn default() -> Minimap {
+        Minimap::new()
+    }
+}
+
+impl Minimap {
+    pub fn new() -> Minimap {
+        Minimap {
+            visible: true,
+            seen: Vec::new(),
+            zoom: 1,
+            size: 160.0,
+            pings: Vec::new(),
+            ping_time: 90,
+            min_volume: 0.05,
+        }
+    }
+
+    pub fn cycle_zoom(&mut self) {
+        self.zoom = (self.zoom + 1) % ZOOMS.len();
+    }
+
+    /// Forget everything (new map)
+    pub fn reset(&mut self) {
+        self.seen.clear();
+        self.pings.clear();
+    }
+
+    /// Marks the walls in the sight cone of the player as seen and adds pings for the sounds heard
+    pub fn update(&mut self, player: &Player, map: &Map, sounds: &[SoundEvent]) {
+        if self.seen.len() != map.walls.len() {
+            self.seen.resize(map.walls.len(), false);
+        }
+        for &i in player.wall_cone.iter().flatten() {
+            self.seen[i] = true;
+        }
+
+        for p in self.pings.iter_mut() {
+            p.age += 1;
+        }
+        let ping_time = self.ping_time;
+        self.pings.retain(|p| p.age < ping_time);
+        for sound in sounds {
+            // do not ping the sounds made by the player
+            if sound.pos.distance(player.pos) <= player.radius {
+                continue;
+            }
+            if sound.volume_at(map, player.pos) >= self.min_volume {
+                self.pings.push(Ping {
+                    kind: sound.kind,
+                    pos: sound.pos,
+                    age: 0,
+                });
+            }
+        }
+    }
+
+    /// Draws the minimap with its top left corner at `corner` on the screen
+    pub fn draw(
+        &self,
+        c: &Context,
+        g: &mut G2d,
+        corner: [f64; 2],
+        map: &Map,
+        player: &Player,
+        teammates: &[Vector<f64>],
+    ) {
+        if !self.visible {
+            return;
+        }
+        let s = self.size;
+        let rect = [corner[0], corner[1], s, s];
+        let zoom = ZOOMS[self.zoom];
+        let center = [corner[0] + s / 2.0, corner[1] + s / 2.0];
+        let to_screen = |p: Vector<f64>| {
+            [
+                center[0] + (p.x - player.pos.x) * zoom,
+                center[1] + (p.y - player.pos.y) * zoom,
+            ]
+        };
+
+        rectangle([0.0, 0.0, 0.0, 0.6], rect, c.transform, g);
+
+        for (wall, _) in map.walls.iter().zip(self.seen.iter()).filter(|(_, s)| **s) {
+            if wall.is_broken() {
+                continue;
+            }
+            let (a, b) = (to_screen(wall.p1), to_screen(wall.p2));
+            if let Some(l) = clip(rect, [a[0], a[1], b[0], b[1]]) {
+                let mut col = wall.material.color;
+                col[3] = if wall.is_open() { 0.3 } else { 0.9 };
+                line(col, 0.5, l, c.transform, g);
+            }
+        }
+
+        for ping in self.pings.iter() {
+            let p = to_screen(ping.pos);
+            let t = ping.age as f64 / self.ping_time as f64;
+            let r = 3.0 + 8.0 * t;
+            let mut col = ping.kind.color();
+            col[3] = (1.0 - t) as f32;
+            // pings outside of the minimap stick to the border
+            let x = p[0].clamp(rect[0] + r, rect[0] + s - r);
+            let y = p[1].clamp(rect[1] + r, rect[1] + s - r);
+            let points = circle(x, y, r);
+            for w in points.windows(2) {
+                line(
+                    col,
+                    0.5,
+                    [w[0][0], w[0][1], w[1][0], w[1][1]],
+                    c.transform,
+                    g,
+                );
+            }
+        }
+
+        for &mate in teammates {
+            let p = to_screen(mate);
+            if p[0] > rect[0] && p[0] < rect[0] + s && p[1] > rect[1] && p[1] < rect[1] + s {
+                ellipse(
+                    [0.2, 0.6, 1.0, 1.0],
+                    [p[0] - 2.0, p[1] - 2.0, 4.0, 4.0],
+                    c.transform,
+                    g,
+                );
+            }
+        }
+
+        // the player and where it is facing
+        let h = player.heading.normalise();
+        let (tip, left, right) = (h * 6.0, h.rotate_z(2.5) * 4.0, h.rotate_z(-2.5) * 4.0);
+        for (a, b) in [(tip, left), (left, right), (right, tip)] {
+            line(
+                [0.0, 1.0, 0.0, 1.0],
+                0.7,
+                [
+                    center[0] + a.x,
+                    center[1] + a.y,
+                    center[0] + b.x,
+                    center[1] + b.y,
+                ],
+                c.transform,
+                g,
+            );
+        }
+
+        let border = [0.6, 0.6, 0.6, 0.8];
+        let (x0, y0, x1, y1) = (rect[0], rect[1], rect[0] + s, rect[1] + s);
+        for l in [
+            [x0, y0, x1, y0],
+            [x1, y0, x1, y1],
+            [x1, y1, x0, y1],
+            [x0, y1, x0, y0],
+        ] {
+            line(border, 0.5, l, c.transform, g);
+        }
+    }
+}
+
+/// Points of a small circle (closed)
+fn circle(x: f64, y: f64, r: f64) -> Vec<[f64; 2]> {
+    (0..=12)
+        .map(|i| {
+            let a = i as f64 * 2.0 * PI / 12.0;
+            [x + r * a.cos(), y + r * a.sin()]
+        })
+        .collect()
+}
+
+/// Part of a segment inside a rectangle (Liang-Barsky)
+fn clip(rect: [f64; 4], l: [f64; 4]) -> Option<[f64; 4]> {
+    let (dx, dy) = (l[2] - l[0], l[3] - l[1]);
+    let (mut t0, mut t1) = (0.0, 1.0);
+    for (p, q) in [
+        (-dx, l[0] - rect[0]),
+        (dx, rect[0] + rect[2] - l[0]),
+        (-dy, l[1] - rect[1]),
+        (dy, rect[1] + rect[3] - l[1]),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let t = q / p;
+        if p < 0.0 {
+            t0 = f64::max(t0, t);
+        } else {
+            t1 = f64::min(t1, t);
+        }
+        if t0 > t1 {
+            return None;
+        }
+    }
+    Some([
+        l[0] + t0 * dx,
+        l[1] + t0 * dy,
+        l[0] + t1 * dx,
+        l[1] + t1 * dy,
+    ])
+}
@@ -0,0 +1,314 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use math_vector::Vector;
+use ray_tracing::segments_intersect;
+
+use crate::map::{wall::Wall, Map};
+
+/// Walkability grid built from the map geometry, used for reachability and path finding
+pub struct NavGrid {
+    pub origin: Vector<f64>, // world position of the top left corner of the grid
+    pub cell: f64,           // size of a cell
+    pub width: usize,        // number of columns
+    pub height: usize,       // number of rows
+    pub radius: f64,         // radius of the agents walking on the grid
+    pub walkable: Vec<bool>, // whether an agent can stand in the middle of the cell
+    links: Vec<[bool; 2]>,   // whether an agent can go to the cell on the right / below
+}
+
+/// Open node of the A* search
+#[derive(PartialEq)]
+struct Node {
+    cost: f64,
+    index: usize,
+}
+
+impl Eq for Node {}
+
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost) // min-heap
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl NavGrid {
+    /// Build the grid over everything in the map (walls, spawns and elements)
+    pub fn new(map: &Map, cell: f64, radius: f64) -> NavGrid {
+        let b = map.bounds();
+        let (mut x0, mut y0) = (b.pos.x - b.width, b.pos.y - b.height);
+        let (mut x1, mut y1) = (b.pos.x + b.width, b.pos.y + b.height);
+        let points = map
+            .walls
+            .iter()
+            .flat_map(|w| [w.p1, w.p2])
+            .chain(map.spawns.iter().map(|s| s.pos))
+            .chain(map.elements.iter().map(|e| e.pos));
+        for p in points {
+            x0 = f64::min(x0, p.x);
+            y0 = f64::min(y0, p.y);
+            x1 = f64::max(x1, p.x);
+            y1 = f64::max(y1, p.y);
+        }
+
+        let width = ((x1 - x0) / cell).ceil() as usize + 2;
+        let height = ((y1 - y0) / cell).ceil() as usize + 2;
+        let mut grid = NavGrid {
+            origin: Vector::new(x0 - cell, y0 - cell, 0.0),
+            cell,
+            width,
+            height,
+            radius,
+            walkable: vec![true; width * height],
+            links: vec![[false; 2]; width * height],
+        };
+        grid.rebuild(map);
+        grid
+    }
+
+    /// Recompute walkability (after the geometry of the map changed)
+    pub fn rebuild(&mut self, map: &Map) {
+        let walls = map
+            .walls
+            .iter()
+            .filter(|w| w.blocks_movement())
+            .copied()
+            .collect::<Vec<Wall>>();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let c = self.center(x, y);
+                self.walkable[y * self.width + x] = !map.is_inside_obstacle(c)
+                    && walls.iter().all(|w| w.distance_to(c) >= self.radius);
+            }
+        }
+
+        let crosses = |a: Vector<f64>, b: Vector<f64>| {
+            walls
+                .iter()
+                .any(|w| segments_intersect(a, b, w.p1, w.p2).is_some())
+        };
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let i = y * self.width + x;
+                let c = self.center(x, y);
+                let right = x + 1 < self.width
+                    && self.walkable[i]
+                    && self.walkable[i + 1]
+                    && !crosses(c, self.center(x + 1, y));
+                let down = y + 1 < self.height
+                    && self.walkable[i]
+                    && self.walkable[i + self.width]
+                    && !crosses(c, self.center(x, y + 1));
+                self.links[i] = [right, down];
+            }
+        }
+    }
+
+    /// World position of the center of a cell
+    pub fn center(&self, x: usize, y: usize) -> Vector<f64> {
+        Vector::new(
+            self.origin.x + (x as f64 + 0.5) * self.cell,
+            self.origin.y + (y as f64 + 0.5) * self.cell,
+            0.0,
+        )
+    }
+
+    /// Cell containing a world position (if it is on the grid)
+    pub fn cell_of(&self, pos: Vector<f64>) -> Option<(usize, usize)> {
+        let x = ((pos.x - self.origin.x) / self.cell).floor();
+        let y = ((pos.y - self.origin.y) / self.cell).floor();
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+
+    pub fn is_walkable(&self, pos: Vector<f64>) -> bool {
+        self.cell_of(pos)
+            .is_some_and(|(x, y)| self.walkable[y * self.width + x])
+    }
+
+    /// Cells that can be reached in one step from a cell (diagonals are allowed around free corners)
+    pub fn neighbors(&self, index: usize) -> Vec<usize> {
+        let w = self.width;
+        let (x, y) = (index % w, index / w);
+        let right = self.links[index][0];
+        let down = self.links[index][1];
+        let left = x > 0 && self.links[index - 1][0];
+        let up = y > 0 && self.links[index - w][1];
+
+        let mut n = Vec::with_capacity(8);
+        if right {
+            n.push(index + 1);
+        }
+        if down {
+            n.push(index + w);
+        }
+        if left {
+            n.push(index - 1);
+        }
+        if up {
+            n.push(index - w);
+        }
+        // diagonals: both ways around the corner must be open
+        if right && down && self.links[index + 1][1] && self.links[index + w][0] {
+            n.push(index + w + 1);
+        }
+        if left && down && self.links[index - 1][1] && self.links[index + w - 1][0] {
+            n.push(index + w - 1);
+        }
+        if right && up && self.links[index - w + 1][1] && self.links[index - w][0] {
+            n.push(index - w + 1);
+        }
+        if left && up && self.links[index - w - 1][1] && self.links[index - w - 1][0] {
+            n.push(index - w - 1);
+        }
+        n
+    }
+
+    /// All the cells reachable from a position
+    pub fn reachable(&self, from: Vector<f64>) -> Vec<bool> {
+        let mut seen = vec![false; self.walkable.len()];
+        let start = match self.cell_of(from) {
+            Some((x, y)) if self.walkable[y * self.width + x] => y * self.width + x,
+            _ => return seen,
+        };
+        let mut stack = vec![start];
+        seen[start] = true;
+        while let Some(i) = stack.pop() {
+            for n in self.neighbors(i) {
+                if !seen[n] {
+                    seen[n] = true;
+                    stack.push(n);
+                }
+            }
+        }
+        seen
+    }
+
+    /// Shortest path (A*) between two positions as a list of cell centers
+    pub fn find_path(&self, from: Vector<f64>, to: Vector<f64>) -> Option<Vec<Vector<f64>>> {
+        self.find_path_with(from, to, |_| 0.0)
+    }
+
+    /// Same as `find_path` with an extra cost for entering each cell (danger, crowding, ...)
+    pub fn find_path_with(
+        &self,
+        from: Vector<f64>,
+        to: Vector<f64>,
+        extra: impl Fn(usize) -> f64,
+    ) -> Option<Vec<Vector<f64>>> {
+        let (sx, sy) = self.cell_of(from)?;
+        let (gx, gy) = self.cell_of(to)?;
+        let (start, goal) = (sy * self.width + sx, gy * self.width + gx);
+        if !self.walkable[start] || !self.walkable[goal] {
+            return None;
+        }
+
+        let pos = |i: usize| self.center(i % self.width, i / self.width);
+        let h = |i: usize| pos(i).distance(pos(goal));
+
+        let mut cost = vec![f64::INFINITY; self.walkable.len()];
+        let mut came_from = vec![usize::MAX; self.walkable.len()];
+        let mut open = BinaryHeap::new();
+        cost[start] = 0.0;
+        open.push(Node {
+            cost: h(start),
+            index: start,
+        });
+
+        while let Some(Node { index, .. }) = open.pop() {
+            if index == goal {
+                let mut path = vec![to];
+                let mut i = came_from[goal];
+                while i != usize::MAX && i != start {
+                    path.push(pos(i));
+                    i = came_from[i];
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for n in self.neighbors(index) {
+                let c = cost[index] + pos(index).distance(pos(n)) + extra(n);
+                if c < cost[n] {
+                    cost[n] = c;
+                    came_from[n] = index;
+                    open.push(Node {
+                        cost: c + h(n),
+                        index: n,
+                    });
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(x: f64, y: f64) -> Vector<f64> {
+        Vector::new(x, y, 0.0)
+    }
+
+    /// Square room of 200 units split by a wall at x = 0, with a door of `door` at the bottom
+    fn room(door: f64) -> Map {
+        let mut map = Map::new();
+        let corners = [
+            v(-100.0, -100.0),
+            v(100.0, -100.0),
+            v(100.0, 100.0),
+            v(-100.0, 100.0),
+        ];
+        for i in 0..4 {
+            map.walls.push(Wall::new(corners[i], corners[(i + 1) % 4]));
+        }
+        map.walls
+            .push(Wall::new(v(0.0, -100.0), v(0.0, 100.0 - door)));
+        map
+    }
+
+    #[test]
+    fn path_goes_through_the_door() {
+        let grid = NavGrid::new(&room(60.0), 10.0, 8.0);
+        let (from, to) = (v(-50.0, -50.0), v(50.0, -50.0));
+        let path = grid.find_path(from, to).expect("no path");
+        assert_eq!(path.last(), Some(&to));
+        assert!(
+            path.iter().any(|p| p.y > 40.0),
+            "the path does not use the door"
+        );
+        // neighbouring cells, the last point is the goal itself
+        for step in path[..path.len() - 1].windows(2) {
+            let d = step[0].distance(step[1]);
+            assert!(
+                d <= grid.cell * 1.5,
+                "step from {:?} to {:?}",
+                step[0],
+                step[1]
+            );
+        }
+    }
+
+    #[test]
+    fn no_path_through_walls() {
+        let grid = NavGrid::new(&room(0.0), 10.0, 8.0);
+        assert!(grid.find_path(v(-50.0, -50.0), v(50.0, -50.0)).is_none());
+        assert!(grid.find_path(v(-50.0, -50.0), v(-50.0, 50.0)).is_some());
+    }
+
+    #[test]
+    fn no_path_from_or_to_a_wall() {
+        let grid = NavGrid::new(&room(60.0), 10.0, 8.0);
+        assert!(grid.find_path(v(0.0, -50.0), v(50.0, -50.0)).is_none());
+        assert!(grid.find_path(v(-50.0, -50.0), v(0.0, -50.0)).is_none());
+        assert!(grid.find_path(v(-50.0, -50.0), v(1e6, 0.0)).is_none());
+    }
+}
@@ -1,17 +1,39 @@
 use math_vector::Vector;
 use piston_window::*;
+use rand::Rng;
 
 use ray_tracing::{Ray as RayTracingRay, Wall as RayTracingWall};
 
-use crate::{map::wall::Wall, PI, RAYS};
+use crate::{
+    input::Action,
+    map::{element::ElementKind, wall::Wall},
+    projectile::{sweep_circle, ProjectileKind},
+    PI, RAYS,
+};
 
 pub struct Player {
-    pub max_health: f64,  // maximum health of player
-    pub health: f64,      // current health of player
-    pub ammo: u64,        // current ammo of player
-    pub in_mag: u64,      // current ammo in magazine
-    pub fire_rate: u64,   // fire rate of player (number of frames between shots)
-    pub reload_time: u64, // reload time of player (number of frames between reloading)
+    pub max_health: f64,      // maximum health of player
+    pub health: f64,          // current health of player
+    pub max_armor: f64,       // maximum armor of player
+    pub armor: f64,           // current armor of player
+    pub max_ammo: u64,        // maximum ammo the player can carry (not counting the magazine)
+    pub ammo: u64,            // current ammo of player
+    pub in_mag: u64,          // current ammo in magazine
+    pub mag_size: u64,        // size of the magazine
+    pub damage: f64,          // damage of one bullet
+    pub range: f64,           // maximum distance of a shot
+    pub fire_cooldown: u64,   // frames left before the next shot
+    pub reload_cooldown: u64, // frames left before the reload is over
+    pub fire_rate: u64,       // fire rate of player (number of frames between shots)
+    pub reload_time: u64,     // reload time of player (number of frames between reloading)
+    pub weapon: String,       // weapon in hand (name in the weapon catalogue)
+    pub weapons: Vec<String>, // weapons picked up by the player
+    pub pellets: u64,         // bullets per shot
+    pub penetration: f64,     // multiplier of the damage kept when going through walls
+    pub grenades: u64,        // grenades left
+    pub throw_cooldown: u64,  // frames left before the next grenade can be thrown
+
+    pub projectile: Option<ProjectileKind>, // fired instead of bullets (none for hitscan)
 
     pub pos: Vector<f64>, // position of player
     pub vel: Vector<f64>, // velocity of player
@@ -32,9 +54,21 @@ pub struct Player {
     pub health_bar_height: f64,      // height of health bar
     pub health_bar_pos: Vector<f64>, // position of health bar (relative to center of player)
 
-    pub sight_cone: Vec<Vector<f64>>, // points of sight cone
-    pub color_cone: Vec<[f32; 4]>,    // colors of rays in sight cone
-    pub hit_cone: Vec<bool>,          // whether ray hit wall in sight cone
+    pub sight_cone: Vec<Vector<f64>>,  // points of sight cone
+    pub color_cone: Vec<[f32; 4]>,     // colors of rays in sight cone
+    pub hit_cone: Vec<bool>,           // whether ray hit wall in sight cone
+    pub wall_cone: Vec<Option<usize>>, // index of the wall hit by each ray (if in range)
+}
+
+/// A bullet or a projectile fired by a player, with the stats of the weapon
+#[derive(Clone, Copy, Debug)]
+pub struct Shot {
+    pub pos: Vector<f64>,                   // where it is fired from
+    pub dir: Vector<f64>,                   // direction, in the cone of fire
+    pub damage: f64,                        // damage of a bullet or of a direct hit
+    pub range: f64,                         // maximum distance of a bullet
+    pub penetration: f64,                   // damage kept through walls (bullets)
+    pub projectile: Option<ProjectileKind>, // none for bullets (hitscan)
 }
 
 impl Player {
@@ -42,10 +76,26 @@ impl Player {
         Player {
             max_health: 100.0,
             health: 100.0,
-            ammo: 0,
-            in_mag: 0,
+            max_armor: 100.0,
+            armor: 0.0,
+            max_ammo: 300,
+            ammo: 90,
+            in_mag: 30,
+            mag_size: 30,
+            damage: 20.0,
+            range: 400.0,
+            fire_cooldown: 0,
+            reload_cooldown: 0,
             fire_rate: 30,
             reload_time: 600,
+            weapon: String::new(),
+            weapons: Vec::new(),
+            pellets: 1,
+            penetration: 1.0,
+            grenades: 3,
+            throw_cooldown: 0,
+
+            projectile: None,
 
             pos: Vector::default(),
             vel: Vector::default(),
@@ -69,6 +119,7 @@ impl Player {
             sight_cone: Vec::new(),
             color_cone: Vec::new(),
             hit_cone: Vec::new(),
+            wall_cone: Vec::new(),
         }
     }
 
@@ -86,12 +137,150 @@ impl Player {
         }
     }
 
+    /// Applies a map element to the player, returns false if it is of no use
+    pub fn pick_up(&mut self, kind: &ElementKind) -> bool {
+        match kind {
+            ElementKind::Ammo(n) => {
+                if self.ammo >= self.max_ammo {
+                    return false;
+                }
+                self.ammo = u64::min(self.ammo + n, self.max_ammo);
+            }
+            ElementKind::Health(h) => {
+                if self.health >= self.max_health {
+                    return false;
+                }
+                self.health = f64::min(self.health + h, self.max_health);
+            }
+            ElementKind::Armor(a) => {
+                if self.armor >= self.max_armor {
+                    return false;
+                }
+                self.armor = f64::min(self.armor + a, self.max_armor);
+            }
+            ElementKind::Trigger(_) => return false,
+            ElementKind::Weapon { name, ammo } => {
+                if self.weapons.contains(name) && self.ammo >= self.max_ammo {
+                    return false;
+                }
+                if !self.weapons.contains(name) {
+                    self.weapons.push(name.clone());
+                }
+                self.ammo = u64::min(self.ammo + ammo, self.max_ammo);
+            }
+        }
+        true
+    }
+
+    /// Whether the player can shoot right now
+    pub fn can_fire(&self) -> bool {
+        self.fire_cooldown == 0 && self.reload_cooldown == 0 && self.in_mag > 0
+    }
+
+    /// Shoots, returns a shot for each pellet (somewhere in the cone of fire)
+    pub fn fire(&mut self, rng: &mut impl Rng) -> Vec<Shot> {
+        if !self.can_fire() {
+            if self.in_mag == 0 {
+                self.reload();
+            }
+            return Vec::new();
+        }
+        self.in_mag -= 1;
+        self.fire_cooldown = self.fire_rate;
+
+        let shots = (0..self.pellets)
+            .map(|_| {
+                let spread = rng.gen_range(-self.accuracy..=self.accuracy);
+                Shot {
+                    pos: self.pos,
+                    dir: self.heading.rotate_z(spread).normalise(),
+                    damage: self.damage,
+                    range: self.range,
+                    penetration: self.penetration,
+                    projectile: self.projectile,
+                }
+            })
+            .collect();
+        self.decrease_accuracy(); // recoil
+        shots
+    }
+
+    /// Throws a grenade if there is one left, returns its direction
+    pub fn throw(&mut self) -> Option<Vector<f64>> {
+        if self.grenades == 0 || self.throw_cooldown > 0 {
+            return None;
+        }
+        self.grenades -= 1;
+        self.throw_cooldown = 60;
+        Some(self.heading.normalise())
+    }
+
+    /// Starts reloading if there is something to reload
+    pub fn reload(&mut self) {
+        if self.reload_cooldown == 0 && self.in_mag < self.mag_size && self.ammo > 0 {
+            self.reload_cooldown = self.reload_time;
+        }
+    }
+
+    /// Ticks the fire and reload timers
+    fn update_weapon(&mut self) {
+        if self.fire_cooldown > 0 {
+            self.fire_cooldown -= 1;
+        }
+        if self.throw_cooldown > 0 {
+            self.throw_cooldown -= 1;
+        }
+        if self.reload_cooldown > 0 {
+            self.reload_cooldown -= 1;
+            if self.reload_cooldown == 0 {
+                let n = u64::min(self.mag_size - self.in_mag, self.ammo);
+                self.in_mag += n;
+                self.ammo -= n;
+            }
+        }
+    }
+
+    /// Applies damage to the player (armor absorbs half of it while it lasts)
+    pub fn take_damage(&mut self, amount: f64) {
+        let absorbed = f64::min(amount * 0.5, self.armor);
+        self.armor -= absorbed;
+        self.health = f64::max(self.health - (amount - absorbed), 0.0);
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.health <= 0.0
+    }
+
+    /// Cancels the part of the velocity that would make the player go through walls
+    pub fn collide(&mut self, walls: &[Wall]) {
+        let blocked = |pos: Vector<f64>| {
+            walls
+                .iter()
+                .any(|w| w.blocks_movement() && w.distance_to(pos) < self.radius)
+        };
+
+        if !blocked(self.pos + self.vel) {
+            return;
+        }
+        // try to slide along the wall
+        let vx = Vector::new(self.vel.x, 0.0, 0.0);
+        let vy = Vector::new(0.0, self.vel.y, 0.0);
+        if !blocked(self.pos + vx) {
+            self.vel = vx;
+        } else if !blocked(self.pos + vy) {
+            self.vel = vy;
+        } else {
+            self.vel *= 0.0;
+        }
+    }
+
     pub fn stop_player(&mut self) {
         self.vel *= 0.0;
         self.rot = 0.0;
     }
 
     pub fn update_player(&mut self) {
+        self.update_weapon();
         self.pos += self.vel;
         self.heading = self.heading.rotate_z(self.rot);
 
@@ -100,6 +289,14 @@ impl Player {
         } else {
             self.decrease_accuracy();
         }
+        // turning faster than with the keys (mouse aiming) costs more accuracy
+        let turn = f64::abs(self.rot) / self.rot_vel;
+        if turn > 1.0 {
+            self.accuracy = f64::min(
+                self.accuracy + self.accuracy_rec * (turn - 1.0),
+                self.max_acc,
+            );
+        }
 
         self.stop_player();
     }
@@ -116,11 +313,51 @@ impl Player {
         self.rot = dir * self.rot_vel;
     }
 
+    /// Turns towards a point by at most `max_turn`, returns true once facing it
+    pub fn turn_towards(&mut self, target: Vector<f64>, max_turn: f64) -> bool {
+        let to = target - self.pos;
+        if to.length_squared() < 1e-8 {
+            return true;
+        }
+        let mut diff = to.y.atan2(to.x) - self.heading.y.atan2(self.heading.x);
+        if diff > PI {
+            diff -= 2.0 * PI;
+        } else if diff < -PI {
+            diff += 2.0 * PI;
+        }
+        self.rot = diff.clamp(-max_turn, max_turn);
+        f64::abs(diff) <= max_turn
+    }
+
+    /// Whether a circle is in the sight cone of the player (as computed by `compute_view`)
+    pub fn sees(&self, pos: Vector<f64>, radius: f64) -> bool {
+        self.pos.distance(pos) <= self.fov_radius + radius
+            && self
+                .sight_cone
+                .iter()
+                .any(|&p| sweep_circle(self.pos, p, pos, radius).is_some())
+    }
+
+    /// Movement and weapon handling of an action, `value` is between 0 and 1 (analog sticks)
+    pub fn act(&mut self, action: Action, value: f64) {
+        match action {
+            Action::MoveForward => self.move_player(value),
+            Action::MoveBackward => self.move_player(-value),
+            Action::StrafeLeft => self.slide_player(-value),
+            Action::StrafeRight => self.slide_player(value),
+            Action::TurnLeft => self.turn_player(-value),
+            Action::TurnRight => self.turn_player(value),
+            Action::Reload => self.reload(),
+            _ => (),
+        }
+    }
+
     /// Calculates the points of sight cone of the player
     pub fn compute_view(&mut self, walls: &Vec<Wall>) {
         self.sight_cone.clear();
         self.color_cone.clear();
         self.hit_cone.clear();
+        self.wall_cone.clear();
 
         let heading = self.heading;
         let fov = self.fov;
@@ -139,13 +376,13 @@ impl Player {
             let mut ray = RayTracingRay::new(self.pos, dir);
             let rtwalls = walls
                 .iter()
-                .map(|w| RayTracingWall::new(w.p1, w.p2))
+                .map(|w| w.to_rt())
                 .collect::<Vec<RayTracingWall>>();
 
-            let pt = ray.look(&rtwalls);
+            let pt = ray.look_index(&rtwalls);
             let mut hit = false;
             let mut p = match pt {
-                Some(p) => {
+                Some((_, p)) => {
                     hit = true;
                     p
                 }
@@ -162,6 +399,8 @@ impl Player {
             self.sight_cone.push(Vector::new(p.x, p.y, 0.0));
             self.color_cone.push(col);
             self.hit_cone.push(hit && d <= self.fov_radius);
+            self.wall_cone
+                .push(pt.filter(|_| d <= self.fov_radius).map(|(i, _)| i));
         }
     }
 
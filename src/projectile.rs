@@ -0,0 +1,305 @@
+use math_vector::Vector;
+use piston_window::*;
+use ray_tracing::segments_intersect;
+
+use crate::map::wall::Wall;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProjectileKind {
+    Grenade, // bounces around and explodes when the fuse runs out
+    Rocket,  // fast, explodes on impact
+    Plasma,  // slow bolt, bounces once, loses power as it travels
+}
+
+/// Side of whoever fired a projectile (it cannot hit its own side directly)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Owner {
+    Player,
+    Enemy,
+}
+
+/// Who fired a shot (only the kills of the human player are credited to it)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shooter {
+    Player,     // the human player
+    Bot(Owner), // a bot of either side
+    Enemy,      // an enemy of the waves
+}
+
+/// Something a projectile can hit (also what the bots know about the combatants)
+#[derive(Clone, Copy, Debug)]
+pub struct Target {
+    pub side: Owner,
+    pub pos: Vector<f64>,
+    pub radius: f64,
+    pub health: f64, // fraction of the maximum health
+}
+
+/// What stopped a projectile
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Impact {
+    Wall(usize),   // index of the wall
+    Target(usize), // index in the targets
+    Expired,       // end of its lifetime
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Projectile {
+    pub kind: ProjectileKind,
+    pub shooter: Shooter,
+    pub pos: Vector<f64>,
+    pub vel: Vector<f64>,
+    pub life: u64,         // frames left
+    pub bounces: u32,      // bounces left
+    pub damage: f64,       // damage of a direct hit
+    pub blast_radius: f64, // area of effect (0 for none)
+    pub blast_damage: f64, // damage at the center of the blast
+}
+
+/// Explosion effect (the damage is applied when it is created)
+#[derive(Clone, Copy, Debug)]
+pub struct Explosion {
+    pub pos: Vector<f64>,
+    pub radius: f64,
+    pub age: u64,
+}
+
+impl ProjectileKind {
+    pub fn from_name(name: &str) -> Option<ProjectileKind> {
+        match name {
+            "grenade" => Some(ProjectileKind::Grenade),
+            "rocket" => Some(ProjectileKind::Rocket),
+            "plasma" => Some(ProjectileKind::Plasma),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            ProjectileKind::Grenade => "grenade",
+            ProjectileKind::Rocket => "rocket",
+            ProjectileKind::Plasma => "plasma",
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        match self {
+            ProjectileKind::Grenade => 6.0,
+            ProjectileKind::Rocket => 8.0,
+            ProjectileKind::Plasma => 4.0,
+        }
+    }
+
+    /// Speed kept from one frame to the next
+    pub fn drag(&self) -> f64 {
+        match self {
+            ProjectileKind::Grenade => 0.97,
+            _ => 1.0,
+        }
+    }
+
+    /// Damage kept from one frame to the next (drop-off)
+    pub fn falloff(&self) -> f64 {
+        match self {
+            ProjectileKind::Plasma => 0.99,
+            _ => 1.0,
+        }
+    }
+
+    pub fn color(&self) -> [f32; 4] {
+        match self {
+            ProjectileKind::Grenade => [0.4, 0.6, 0.2, 1.0],
+            ProjectileKind::Rocket => [1.0, 0.5, 0.1, 1.0],
+            ProjectileKind::Plasma => [0.3, 0.8, 1.0, 1.0],
+        }
+    }
+}
+
+impl Shooter {
+    /// Side of the shooter, that its shots do not hit
+    pub fn side(&self) -> Owner {
+        match self {
+            Shooter::Player => Owner::Player,
+            Shooter::Bot(side) => *side,
+            Shooter::Enemy => Owner::Enemy,
+        }
+    }
+}
+
+impl Projectile {
+    pub fn new(
+        kind: ProjectileKind,
+        shooter: Shooter,
+        pos: Vector<f64>,
+        dir: Vector<f64>,
+    ) -> Projectile {
+        let (life, bounces, damage, blast_radius, blast_damage) = match kind {
+            ProjectileKind::Grenade => (150, 5, 0.0, 80.0, 90.0),
+            ProjectileKind::Rocket => (120, 0, 30.0, 60.0, 80.0),
+            ProjectileKind::Plasma => (100, 1, 35.0, 0.0, 0.0),
+        };
+        Projectile {
+            kind,
+            shooter,
+            pos,
+            vel: dir.normalise() * kind.speed(),
+            life,
+            bounces,
+            damage,
+            blast_radius,
+            blast_damage,
+        }
+    }
+
+    /// Moves the projectile for one frame, with swept tests against the walls and the targets
+    pub fn step(&mut self, walls: &[Wall], targets: &[Target]) -> Option<Impact> {
+        if self.life == 0 {
+            return Some(Impact::Expired);
+        }
+        self.life -= 1;
+        let (a, b) = (self.pos, self.pos + self.vel);
+
+        // closest wall crossed by the movement
+        let wall = walls
+            .iter()
+            .enumerate()
+            .filter(|(_, w)| w.blocks_movement())
+            .filter_map(|(i, w)| segments_intersect(a, b, w.p1, w.p2).map(|p| (i, p)))
+            .map(|(i, p)| (i, p, a.distance(p)))
+            .min_by(|x, y| x.2.total_cmp(&y.2));
+        // closest target touched by the movement
+        let target = targets
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.side != self.shooter.side())
+            .filter_map(|(i, t)| sweep_circle(a, b, t.pos, t.radius).map(|d| (i, d)))
+            .min_by(|x, y| x.1.total_cmp(&y.1));
+
+        match (wall, target) {
+            (_, Some((i, d))) if wall.is_none_or(|w| d <= w.2) => {
+                self.pos = a + self.vel.normalise() * d;
+                Some(Impact::Target(i))
+            }
+            (Some((i, p, _)), _) => {
+                if self.bounces == 0 {
+                    // back off the wall so that the blast is on the side it came from
+                    self.pos = p - self.vel.normalise();
+                    return Some(Impact::Wall(i));
+                }
+                self.bounces -= 1;
+                self.bounce(&walls[i], p);
+                None
+            }
+            _ => {
+                self.pos = b;
+                self.vel *= self.kind.drag();
+                self.damage *= self.kind.falloff();
+                None
+            }
+        }
+    }
+
+    /// Reflects the velocity on a wall hit at `p`
+    fn bounce(&mut self, wall: &Wall, p: Vector<f64>) {
+        let d = (wall.p2 - wall.p1).normalise();
+        let n = Vector::new(-d.y, d.x, 0.0);
+        let dot = self.vel.x * n.x + self.vel.y * n.y;
+        self.vel = self.vel - n * (2.0 * dot);
+        self.vel *= 0.7; // loses some energy
+
+        // step back from the wall so that it is not crossed again
+        let side = if dot > 0.0 { -1.0 } else { 1.0 };
+        self.pos = p + n * side;
+    }
+
+    pub fn draw(&self, _c: &Context, g: &mut G2d, transform: math::Matrix2d) {
+        let r = if self.kind == ProjectileKind::Plasma {
+            3.0
+        } else {
+            2.5
+        };
+        ellipse(
+            self.kind.color(),
+            [self.pos.x - r, self.pos.y - r, r * 2.0, r * 2.0],
+            transform,
+            g,
+        );
+        let tail = self.pos - self.vel * 2.0;
+        let mut col = self.kind.color();
+        col[3] = 0.4;
+        line(
+            col,
+            1.0,
+            [tail.x, tail.y, self.pos.x, self.pos.y],
+            transform,
+            g,
+        );
+    }
+}
+
+/// Distance along the segment `a -> b` at which it first touches a circle
+pub fn sweep_circle(a: Vector<f64>, b: Vector<f64>, center: Vector<f64>, r: f64) -> Option<f64> {
+    let len = a.distance(b);
+    if a.distance(center) <= r {
+        return Some(0.0);
+    }
+    if len == 0.0 {
+        return None;
+    }
+    let dir = (b - a) * (1.0 / len);
+    let t = (center.x - a.x) * dir.x + (center.y - a.y) * dir.y;
+    let closest = a + dir * t;
+    let d2 = (center - closest).length_squared();
+    if d2 > r * r {
+        return None;
+    }
+    let t = t - f64::sqrt(r * r - d2);
+    if (0.0..=len).contains(&t) {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// Whether a blast at `from` reaches `to` (no blocking wall in between)
+pub fn blast_reaches(from: Vector<f64>, to: Vector<f64>, walls: &[Wall]) -> bool {
+    walls
+        .iter()
+        .filter(|w| w.blocks_movement())
+        .all(|w| segments_intersect(from, to, w.p1, w.p2).is_none())
+}
+
+/// Damage of a blast at some distance of its center (linear drop-off)
+pub fn blast_damage(damage: f64, radius: f64, distance: f64) -> f64 {
+    if distance >= radius {
+        return 0.0;
+    }
+    damage * (1.0 - distance / radius)
+}
+
+impl Explosion {
+    pub const DURATION: u64 = 20;
+
+    pub fn new(pos: Vector<f64>, radius: f64) -> Explosion {
+        Explosion {
+            pos,
+            radius,
+            age: 0,
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.age >= Explosion::DURATION
+    }
+
+    pub fn draw(&self, _c: &Context, g: &mut G2d, transform: math::Matrix2d) {
+        let t = self.age as f64 / Explosion::DURATION as f64;
+        let r = self.radius * (0.3 + 0.7 * t);
+        ellipse(
+            [1.0, 0.6, 0.1, (0.6 * (1.0 - t)) as f32],
+            [self.pos.x - r, self.pos.y - r, r * 2.0, r * 2.0],
+            transform,
+            g,
+        );
+    }
+}
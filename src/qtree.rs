@@ -4,6 +4,9 @@ pub mod bounds;
 use bounds::*;
 use piston_window::*;
 
+/// Deepest level of subdivision (more than `size` points at the same spot would divide forever)
+const MAX_DEPTH: usize = 10;
+
 pub struct QTree {
     pub northeast: Option<Box<QTree>>,
     pub northwest: Option<Box<QTree>>,
@@ -16,6 +19,7 @@ pub struct QTree {
 
     pub boundary: Rect,
     pub is_divided: bool,
+    pub depth: usize, // level of subdivision (0 for the root)
 }
 
 impl QTree {
@@ -34,6 +38,7 @@ impl QTree {
                 height,
             },
             is_divided: false,
+            depth: 0,
         }
     }
 
@@ -78,12 +83,22 @@ impl QTree {
             w,
             h,
         )));
+        for child in [
+            &mut self.northeast,
+            &mut self.northwest,
+            &mut self.southeast,
+            &mut self.southwest,
+        ] {
+            if let Some(child) = child.as_mut() {
+                child.depth = self.depth + 1;
+            }
+        }
     }
 
     pub fn insert(&mut self, p: &Point) -> bool {
         if !(self.boundary.contains(p)) {
             false
-        } else if self.points.len() < self.size {
+        } else if self.points.len() < self.size || self.depth >= MAX_DEPTH {
             self.points.push(*p);
             true
         } else {
@@ -0,0 +1,128 @@
+use serde_json::{json, Value};
+
+use crate::{game::Game, input::InputEvent, projectile::Owner, HEIGHT, WIDTH};
+
+/// Everything needed to play a game again: settings, seed and inputs
+#[derive(Clone, Debug)]
+pub struct Replay {
+    pub map: String,
+    pub hero: String,
+    pub seed: u64,
+    pub waves: Value,                   // levels and waves (see `Waves::to_value`)
+    pub weapons: Value,                 // weapon catalogue (see `Catalogue::to_value`)
+    pub ai: Value,                      // enemy behaviour trees (see `Behaviours::to_value`)
+    pub allies: usize,                  // bots playing with the player
+    pub opponents: usize,               // bots playing against the player
+    pub difficulty: String,             // skill of the bots
+    pub camera: String,                 // camera mode (the spectator camera stops the player)
+    pub screen: [f64; 2],               // size of the window (the cursor position depends on it)
+    pub input: Value, // key bindings and control scheme (see `InputMap::to_value`)
+    pub events: Vec<(u64, InputEvent)>, // inputs and the frame they happened at
+}
+
+impl Replay {
+    /// Start recording a game that was just set up on a map
+    pub fn new(map: &str, hero: &str, game: &Game) -> Replay {
+        Replay {
+            map: map.to_string(),
+            hero: hero.to_string(),
+            seed: game.seed,
+            waves: game.waves.as_ref().map_or(Value::Null, |w| w.to_value()),
+            weapons: game.weapons.to_value(),
+            ai: game.behaviours.to_value(),
+            allies: game.bots.iter().filter(|b| b.side == Owner::Player).count(),
+            opponents: game.bots.iter().filter(|b| b.side == Owner::Enemy).count(),
+            difficulty: game
+                .bots
+                .first()
+                .map_or("normal", |b| b.difficulty.name())
+                .to_string(),
+            camera: game.camera.mode.name().to_string(),
+            screen: game.screen,
+            input: game.input.to_value(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, frame: u64, event: InputEvent) {
+        self.events.push((frame, event));
+    }
+
+    /// Events that happened at a given frame
+    pub fn events_at(&self, frame: u64) -> impl Iterator<Item = &InputEvent> {
+        self.events
+            .iter()
+            .filter(move |(f, _)| *f == frame)
+            .map(|(_, e)| e)
+    }
+
+    /// Frame of the last recorded event
+    pub fn last_frame(&self) -> u64 {
+        self.events.last().map_or(0, |(f, _)| *f)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let events = self
+            .events
+            .iter()
+            .map(|(frame, e)| {
+                let mut v = e.to_value();
+                v["frame"] = json!(frame);
+                v
+            })
+            .collect::<Vec<Value>>();
+        let v = json!({
+            "map": self.map,
+            "hero": self.hero,
+            "seed": self.seed,
+            "waves": self.waves,
+            "weapons": self.weapons,
+            "ai": self.ai,
+            "bots": {
+                "allies": self.allies,
+                "opponents": self.opponents,
+                "difficulty": self.difficulty,
+            },
+            "camera": self.camera,
+            "screen": self.screen,
+            "input": self.input,
+            "events": events,
+        });
+        let data = serde_json::to_string(&v).map_err(|e| e.to_string())?;
+        std::fs::write(path, data).map_err(|e| format!("could not write {}: {}", path, e))
+    }
+
+    pub fn load(path: &str) -> Result<Replay, String> {
+        let data =
+            std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+        let v: Value = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+
+        let screen = &v["screen"];
+        let mut replay = Replay {
+            map: v["map"].as_str().ok_or("missing map")?.to_string(),
+            hero: v["hero"].as_str().unwrap_or("soldier").to_string(),
+            seed: v["seed"].as_u64().ok_or("missing seed")?,
+            waves: v["waves"].clone(),
+            weapons: v["weapons"].clone(),
+            ai: v["ai"].clone(),
+            allies: v["bots"]["allies"].as_u64().unwrap_or(0) as usize,
+            opponents: v["bots"]["opponents"].as_u64().unwrap_or(0) as usize,
+            difficulty: v["bots"]["difficulty"]
+                .as_str()
+                .unwrap_or("normal")
+                .to_string(),
+            camera: v["camera"].as_str().unwrap_or("heading-up").to_string(),
+            screen: [
+                screen[0].as_f64().unwrap_or(WIDTH as f64),
+                screen[1].as_f64().unwrap_or(HEIGHT as f64),
+            ],
+            input: v["input"].clone(),
+            events: Vec::new(),
+        };
+        for e in v["events"].as_array().cloned().unwrap_or_default() {
+            let frame = e["frame"].as_u64().ok_or("missing frame")?;
+            replay.record(frame, InputEvent::from_value(&e)?);
+        }
+        Ok(replay)
+    }
+}
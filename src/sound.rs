@@ -0,0 +1,61 @@
+use math_vector::Vector;
+
+use crate::map::Map;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SoundKind {
+    Shot,
+    Impact, // bullet hitting a wall
+    Door,   // door opened or closed
+    Explosion,
+}
+
+/// Something that can be heard, emitted during one frame
+#[derive(Clone, Copy, Debug)]
+pub struct SoundEvent {
+    pub kind: SoundKind,
+    pub pos: Vector<f64>,
+    pub range: f64, // distance at which it cannot be heard anymore (without walls)
+
+    pub emitter: Option<usize>, // who made it (index in `Game::targets`, none for the world)
+}
+
+impl SoundKind {
+    pub fn range(&self) -> f64 {
+        match self {
+            SoundKind::Shot => 600.0,
+            SoundKind::Impact => 200.0,
+            SoundKind::Door => 250.0,
+            SoundKind::Explosion => 900.0,
+        }
+    }
+
+    pub fn color(&self) -> [f32; 4] {
+        match self {
+            SoundKind::Shot => [1.0, 0.3, 0.2, 1.0],
+            SoundKind::Impact => [0.8, 0.8, 0.8, 1.0],
+            SoundKind::Door => [0.6, 0.4, 0.2, 1.0],
+            SoundKind::Explosion => [1.0, 0.6, 0.0, 1.0],
+        }
+    }
+}
+
+impl SoundEvent {
+    pub fn new(kind: SoundKind, pos: Vector<f64>, emitter: Option<usize>) -> SoundEvent {
+        SoundEvent {
+            kind,
+            pos,
+            range: kind.range(),
+            emitter,
+        }
+    }
+
+    /// How loud the sound is at a position, between 0 (not heard) and 1 (right next to it)
+    pub fn volume_at(&self, map: &Map, pos: Vector<f64>) -> f64 {
+        let d = self.pos.distance(pos);
+        if d >= self.range {
+            return 0.0;
+        }
+        (1.0 - d / self.range) * map.sound_attenuation(self.pos, pos)
+    }
+}
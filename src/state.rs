@@ -0,0 +1,481 @@
+use piston_window::*;
+use serde_json::Value;
+
+use crate::{
+    ai::Behaviours,
+    cli::Options,
+    game::Game,
+    hero::Hero,
+    hud::error,
+    input::{Action, InputEvent, InputMap, MouseAim},
+    map::tiled,
+    projectile::Owner,
+    replay::Replay,
+    wave::Waves,
+    weapon::Catalogue,
+};
+
+/// Screens of the game
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    Menu,     // map and hero selection
+    Controls, // bindings of the actions, changed from the menu
+    Loading,  // drawn once before the map is loaded
+    Playing,
+    Paused,
+    RoundEnd, // all the waves of the level are cleared
+    GameOver, // the player is dead, results
+}
+
+/// What the menus understand, from the keyboard or a gamepad
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MenuInput {
+    Up,
+    Down,
+    Left,
+    Right,
+    Confirm,
+    Back,
+    Clear,
+}
+
+const MENU: [&str; 5] = ["map", "hero", "controls", "play", "quit"];
+/// Where the controls changed in the menu are saved when `--input` is a preset
+const CONTROLS: &str = "data/controls.json";
+const PAUSE: [&str; 3] = ["resume", "main menu", "quit"];
+
+/// The whole application: the state machine around the game
+pub struct App {
+    pub state: State,
+    pub game: Game,
+    pub opts: Options,          // settings from the command line
+    pub maps: Vec<String>,      // maps that can be picked
+    pub heroes: Vec<Hero>,      // heroes that can be picked
+    pub map: usize,             // selected map
+    pub hero: usize,            // selected hero
+    pub selected: usize,        // highlighted item of the current menu
+    pub input: InputMap,        // controls edited in the menu (from `--input`)
+    pub record: Option<Replay>, // inputs of the current game (with `--record`)
+    pub error: Option<String>,  // why the last game could not start
+    pub quit: bool,             // the window should close
+}
+
+/// Campaign file given on the command line, or endless waves
+pub fn waves_from_options(opts: &Options) -> Result<Waves, String> {
+    match opts.levels.as_ref() {
+        Some(path) => Waves::load(path).map_err(|e| format!("could not load the levels: {}", e)),
+        None => Ok(Waves::endless(opts.bots, opts.bot_kind)),
+    }
+}
+
+/// Set up a game from the options: map, hero, weapons, waves, seed, debug overlays and controls
+pub fn build_game(
+    opts: &Options,
+    map: &str,
+    hero: &Hero,
+    seed: u64,
+    waves: Waves,
+) -> Result<Game, String> {
+    let mut game = Game::with_seed(seed);
+    game.load_map(map)?;
+    hero.apply(&mut game.player);
+    game.weapons = Catalogue::load(&opts.weapons)?;
+    if !game.equip(&hero.weapon) {
+        return Err(format!(
+            "unknown weapon {} of hero {}",
+            hero.weapon, hero.name
+        ));
+    }
+    game.set_behaviours(Behaviours::load(&opts.ai)?);
+    // bots take the heroes in turn
+    let heroes = Hero::all();
+    let sides = std::iter::repeat_n(Owner::Player, opts.allies)
+        .chain(std::iter::repeat_n(Owner::Enemy, opts.opponents));
+    for (i, side) in sides.enumerate() {
+        game.add_bot(side, opts.difficulty, &heroes[i % heroes.len()]);
+    }
+    game.start_waves(waves)?;
+    game.screen = [opts.width as f64, opts.height as f64];
+    game.debug = opts.debug;
+    game.camera.mode = opts.camera;
+    game.input = InputMap::load(&opts.input)?;
+    if opts.mouse_aim && game.input.mouse_aim.is_none() {
+        game.input.mouse_aim = Some(MouseAim::default());
+    }
+    Ok(game)
+}
+
+/// Maps in a folder (native or Tiled), the given map first
+pub fn find_maps(dir: &str, first: &str) -> Vec<String> {
+    let mut maps = vec![first.to_string()];
+    let mut found = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.path().to_string_lossy().replace('\\', "/"))
+                .filter(|p| is_map(p))
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+    found.sort();
+    maps.extend(found.into_iter().filter(|p| p != first));
+    maps
+}
+
+fn is_map(path: &str) -> bool {
+    if path.ends_with(".tmx") {
+        return true;
+    }
+    if !path.ends_with(".json") {
+        return false;
+    }
+    match std::fs::read_to_string(path).map(|d| serde_json::from_str::<Value>(&d)) {
+        Ok(Ok(v)) => v.get("walls").is_some() || tiled::is_tiled(&v),
+        _ => false,
+    }
+}
+
+impl MenuInput {
+    fn from_button(button: Button) -> Option<MenuInput> {
+        match button {
+            Button::Keyboard(Key::Up) => Some(MenuInput::Up),
+            Button::Keyboard(Key::Down) => Some(MenuInput::Down),
+            Button::Keyboard(Key::Left) => Some(MenuInput::Left),
+            Button::Keyboard(Key::Right) => Some(MenuInput::Right),
+            Button::Keyboard(Key::Return) => Some(MenuInput::Confirm),
+            Button::Keyboard(Key::Escape) => Some(MenuInput::Back),
+            Button::Keyboard(Key::Backspace | Key::Delete) => Some(MenuInput::Clear),
+            // gamepad (SDL game controller layout): d-pad, A, B and start
+            Button::Controller(b) => match b.button {
+                11 => Some(MenuInput::Up),
+                12 => Some(MenuInput::Down),
+                13 => Some(MenuInput::Left),
+                14 => Some(MenuInput::Right),
+                0 => Some(MenuInput::Confirm),
+                1 | 6 => Some(MenuInput::Back),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl App {
+    pub fn new(opts: Options) -> App {
+        let heroes = Hero::all();
+        let hero = heroes.iter().position(|h| h.name == opts.hero).unwrap_or(0);
+        // an invalid file is reported when the game starts
+        let input = InputMap::load(&opts.input).unwrap_or_else(|_| InputMap::azerty());
+        let mut app = App {
+            state: State::Menu,
+            game: Game::new(),
+            maps: find_maps("data", &opts.map),
+            heroes,
+            map: 0,
+            hero,
+            selected: 3, // play
+            input,
+            record: None,
+            error: None,
+            quit: false,
+            opts,
+        };
+        app.game.screen = [app.opts.width as f64, app.opts.height as f64];
+        if app.opts.no_menu {
+            app.state = State::Loading;
+        }
+        app
+    }
+
+    /// Load the selected map and start playing
+    fn start(&mut self) {
+        let seed = self.opts.seed.unwrap_or_else(rand::random);
+        let (map, hero) = (&self.maps[self.map], &self.heroes[self.hero]);
+        let game = waves_from_options(&self.opts)
+            .and_then(|waves| build_game(&self.opts, map, hero, seed, waves));
+        match game {
+            Ok(game) => self.game = game,
+            Err(e) => {
+                // back to the menu, the files can be fixed and the game started again
+                self.error = Some(e);
+                self.open(State::Menu);
+                return;
+            }
+        }
+        self.error = None;
+        if self.opts.record.is_some() {
+            self.record = Some(Replay::new(map, &hero.name, &self.game));
+        }
+        self.state = State::Playing;
+    }
+
+    /// Write the recording of the last game (if any)
+    pub fn save_record(&mut self) {
+        if let (Some(record), Some(path)) = (self.record.take(), self.opts.record.as_ref()) {
+            match record.save(path) {
+                Ok(()) => println!("replay written to {}", path),
+                Err(e) => self.error = Some(format!("could not save the replay: {}", e)),
+            }
+        }
+    }
+
+    fn open(&mut self, state: State) {
+        self.state = state;
+        self.selected = 0;
+        if state == State::Menu {
+            self.selected = 3;
+            self.save_record();
+        }
+    }
+
+    pub fn handle_input(&mut self, input: InputEvent) {
+        // the next button or axis goes to the action being rebound
+        if self.state == State::Controls && self.input.is_rebinding().is_some() {
+            match input {
+                InputEvent::Press(Button::Keyboard(Key::Escape)) => self.input.cancel_rebind(),
+                InputEvent::Press(button) => {
+                    self.input.press(button);
+                }
+                InputEvent::Axis(args) => self.input.move_axis(args),
+                _ => (),
+            }
+            return;
+        }
+
+        let menu = match input {
+            InputEvent::Press(button) => MenuInput::from_button(button),
+            _ => None,
+        };
+        let in_game = matches!(
+            self.state,
+            State::Playing | State::Paused | State::RoundEnd | State::GameOver
+        );
+        // presses only go to the game while playing, the rest is needed to release the keys
+        let to_game = if self.state == State::Playing {
+            menu != Some(MenuInput::Back)
+        } else {
+            in_game && !matches!(input, InputEvent::Press(_))
+        };
+        if to_game {
+            if let Some(record) = self.record.as_mut() {
+                record.record(self.game.frame, input);
+            }
+            self.game.handle_input(input);
+        }
+
+        match menu {
+            Some(MenuInput::Back) if self.state == State::Playing => self.open(State::Paused),
+            Some(menu) if self.state != State::Playing => self.navigate(menu),
+            _ => (),
+        }
+    }
+
+    fn navigate(&mut self, input: MenuInput) {
+        let items = match self.state {
+            State::Menu => MENU.len(),
+            State::Controls => Action::ALL.len() + 1, // and back
+            State::Paused => PAUSE.len(),
+            _ => 1,
+        };
+        match input {
+            MenuInput::Up => self.selected = (self.selected + items - 1) % items,
+            MenuInput::Down => self.selected = (self.selected + 1) % items,
+            MenuInput::Left | MenuInput::Right if self.state == State::Menu => {
+                let step = |i: usize, n: usize| {
+                    if input == MenuInput::Left {
+                        (i + n - 1) % n
+                    } else {
+                        (i + 1) % n
+                    }
+                };
+                match MENU[self.selected] {
+                    "map" => self.map = step(self.map, self.maps.len()),
+                    "hero" => self.hero = step(self.hero, self.heroes.len()),
+                    _ => (),
+                }
+            }
+            MenuInput::Clear if self.state == State::Controls => {
+                if let Some(&action) = Action::ALL.get(self.selected) {
+                    self.input.unbind(action);
+                }
+            }
+            MenuInput::Confirm => self.confirm(),
+            MenuInput::Back => match self.state {
+                State::Menu => self.quit = true,
+                State::Controls => self.save_controls(),
+                State::Paused => self.state = State::Playing,
+                _ => self.open(State::Menu),
+            },
+            _ => (),
+        }
+    }
+
+    fn confirm(&mut self) {
+        match (self.state, self.selected) {
+            (State::Menu, 2) => self.open(State::Controls),
+            (State::Menu, 3) => self.state = State::Loading,
+            (State::Menu, 4) => self.quit = true,
+            (State::Controls, i) => match Action::ALL.get(i) {
+                Some(&action) => self.input.start_rebind(action),
+                None => self.save_controls(), // back
+            },
+            (State::Paused, 0) => self.state = State::Playing,
+            (State::Paused, 1) => self.open(State::Menu),
+            (State::Paused, 2) => self.quit = true,
+            (State::RoundEnd, _) => {
+                if self.game.next_level() {
+                    self.state = State::Playing;
+                } else {
+                    self.open(State::GameOver); // campaign complete
+                }
+            }
+            (State::GameOver, _) => self.open(State::Menu),
+            _ => (),
+        }
+    }
+
+    /// Back to the menu, the controls are saved for the next games
+    fn save_controls(&mut self) {
+        let path = if InputMap::preset(&self.opts.input).is_some() {
+            CONTROLS.to_string()
+        } else {
+            self.opts.input.clone()
+        };
+        match self.input.save(&path) {
+            Ok(()) => self.opts.input = path,
+            Err(e) => self.error = Some(format!("could not save the controls: {}", e)),
+        }
+        self.open(State::Menu);
+        self.selected = 2; // controls
+    }
+
+    pub fn update(&mut self) {
+        match self.state {
+            State::Loading => self.start(),
+            State::Playing => {
+                self.game.update();
+                if self.game.game_over {
+                    self.open(State::GameOver);
+                } else if self.game.level_cleared {
+                    self.open(State::RoundEnd);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    pub fn draw(&mut self, c: &Context, g: &mut G2d, glyphs: &mut Glyphs) {
+        let [w, h] = self.game.screen;
+        match self.state {
+            State::Menu => {
+                title(c, g, glyphs, "Bullet Echo", w / 2.0, h / 3.0);
+                let items = [
+                    format!("< map: {} >", self.maps[self.map]),
+                    format!("< hero: {} >", self.heroes[self.hero].name),
+                    "controls".to_string(),
+                    "play".to_string(),
+                    "quit".to_string(),
+                ];
+                self.draw_items(c, g, glyphs, &items);
+                if let Some(e) = self.error.as_ref() {
+                    error(c, g, glyphs, e, w / 2.0, h - 40.0);
+                }
+            }
+            State::Controls => {
+                title(c, g, glyphs, "controls", w / 2.0, h / 6.0);
+                let rebinding = self.input.is_rebinding();
+                let mut items = Action::ALL
+                    .iter()
+                    .map(|&a| {
+                        let bindings = if rebinding == Some(a) {
+                            "press a button or push an axis".to_string()
+                        } else {
+                            let names = self.input.bindings_of(a).into_iter().map(|b| b.name());
+                            names.collect::<Vec<String>>().join(", ")
+                        };
+                        format!("{}: {}", a.name(), bindings)
+                    })
+                    .collect::<Vec<String>>();
+                items.push("back".to_string());
+                for (i, s) in items.iter().enumerate() {
+                    let s = if i == self.selected {
+                        format!("[ {} ]", s)
+                    } else {
+                        s.clone()
+                    };
+                    let y = h / 6.0 + 40.0 + 24.0 * i as f64;
+                    centered(c, g, glyphs, &s, 14, w / 2.0, y);
+                }
+                let help = if rebinding.is_some() {
+                    "esc to cancel"
+                } else {
+                    "enter to rebind, backspace to clear"
+                };
+                centered(c, g, glyphs, help, 14, w / 2.0, h - 40.0);
+            }
+            State::Loading => {
+                title(c, g, glyphs, "loading...", w / 2.0, h / 2.0);
+            }
+            State::Playing => self.game.draw(c, g, glyphs),
+            State::Paused => {
+                self.game.draw(c, g, glyphs);
+                rectangle([0.0, 0.0, 0.0, 0.6], [0.0, 0.0, w, h], c.transform, g);
+                title(c, g, glyphs, "paused", w / 2.0, h / 3.0);
+                let items = PAUSE.map(|s| s.to_string());
+                self.draw_items(c, g, glyphs, &items);
+            }
+            State::RoundEnd | State::GameOver => {
+                self.game.draw(c, g, glyphs);
+                rectangle([0.0, 0.0, 0.0, 0.6], [0.0, 0.0, w, h], c.transform, g);
+                let (heading, next) = if self.state == State::RoundEnd {
+                    (format!("level {} cleared", self.game.level), "next level")
+                } else if self.game.finished {
+                    ("campaign complete".to_string(), "main menu")
+                } else {
+                    ("game over".to_string(), "main menu")
+                };
+                title(c, g, glyphs, &heading, w / 2.0, h / 3.0);
+                let results = [
+                    format!("score {}", self.game.score),
+                    format!("level {}", self.game.level),
+                    format!("kills {}", self.game.kills),
+                    String::new(),
+                    format!("> {} <", next),
+                ];
+                for (i, s) in results.iter().enumerate() {
+                    centered(c, g, glyphs, s, 16, w / 2.0, h / 2.0 + 25.0 * i as f64);
+                }
+            }
+        }
+    }
+
+    fn draw_items(&self, c: &Context, g: &mut G2d, glyphs: &mut Glyphs, items: &[String]) {
+        let [w, h] = self.game.screen;
+        for (i, s) in items.iter().enumerate() {
+            let s = if i == self.selected {
+                format!("[ {} ]", s)
+            } else {
+                s.clone()
+            };
+            centered(c, g, glyphs, &s, 16, w / 2.0, h / 2.0 + 30.0 * i as f64);
+        }
+    }
+}
+
+fn title(c: &Context, g: &mut G2d, glyphs: &mut Glyphs, s: &str, x: f64, y: f64) {
+    centered(c, g, glyphs, s, 32, x, y);
+}
+
+/// Text centered horizontally on `x`
+fn centered(c: &Context, g: &mut G2d, glyphs: &mut Glyphs, s: &str, size: u32, x: f64, y: f64) {
+    let width = glyphs.width(size, s).unwrap_or(0.0);
+    text::Text::new_color([1.0; 4], size)
+        .draw(
+            s,
+            glyphs,
+            &c.draw_state,
+            c.transform.trans(x - width / 2.0, y),
+            g,
+        )
+        .unwrap_or_else(|e| panic!("Failed to draw text: {}", e));
+}
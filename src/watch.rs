@@ -0,0 +1,31 @@
+use std::time::SystemTime;
+
+/// Watches a file for changes, through its modification time
+#[derive(Clone, Debug)]
+pub struct Watch {
+    pub path: String,
+    modified: Option<SystemTime>, // last modification time seen (none if the file is missing)
+}
+
+impl Watch {
+    pub fn new(path: &str) -> Watch {
+        Watch {
+            path: path.to_string(),
+            modified: modified(path),
+        }
+    }
+
+    /// Whether the file was modified since the last call (or since the watch was created)
+    pub fn changed(&mut self) -> bool {
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
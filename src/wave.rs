@@ -0,0 +1,228 @@
+use serde_json::{json, Map as JsonMap, Value};
+
+use crate::enemy::EnemyKind;
+
+const KINDS: [EnemyKind; 3] = [EnemyKind::Grunt, EnemyKind::Scout, EnemyKind::Heavy];
+
+/// Enemies of one wave, spawned one after the other
+#[derive(Clone, Debug, PartialEq)]
+pub struct Wave {
+    pub enemies: Vec<(EnemyKind, usize)>, // kind and count
+}
+
+/// Waves to clear on a map
+#[derive(Clone, Debug, PartialEq)]
+pub struct Level {
+    pub map: Option<String>, // map to play on (the current one if none)
+    pub waves: Vec<Wave>,
+}
+
+/// Progression through levels and waves, gives the enemies to spawn
+#[derive(Clone, Debug)]
+pub struct Waves {
+    pub levels: Vec<Level>,      // campaign (endless generated waves if empty)
+    pub base: usize,             // enemies in the first generated wave
+    pub kind: EnemyKind,         // main kind of the generated waves
+    pub waves_per_level: usize,  // number of generated waves in a level
+    pub level: usize,            // current level (starting at 0)
+    pub wave: usize,             // current wave in the level
+    pub pending: Vec<EnemyKind>, // enemies of the wave not spawned yet
+    pub spawn_delay: u64,        // frames between two spawns
+    timer: u64,                  // frames before the next spawn
+}
+
+impl Wave {
+    pub fn total(&self) -> usize {
+        self.enemies.iter().map(|(_, n)| n).sum()
+    }
+
+    /// `{"grunt": 3, "scout": 1}`
+    pub fn from_value(v: &Value) -> Result<Wave, String> {
+        let mut enemies = Vec::new();
+        for (name, count) in v.as_object().ok_or("a wave must be an object")? {
+            let kind =
+                EnemyKind::from_name(name).ok_or_else(|| format!("unknown enemy kind {}", name))?;
+            let count = count
+                .as_u64()
+                .ok_or_else(|| format!("invalid count of {}", name))?;
+            enemies.push((kind, count as usize));
+        }
+        Ok(Wave { enemies })
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut v = JsonMap::new();
+        for (kind, count) in self.enemies.iter() {
+            v.insert(kind.name().to_string(), json!(count));
+        }
+        Value::Object(v)
+    }
+
+    /// Generated wave: more enemies, and tougher ones, as the level goes up
+    pub fn generate(base: usize, kind: EnemyKind, level: usize, wave: usize) -> Wave {
+        let total = base + level + wave;
+        let mut enemies: Vec<(EnemyKind, usize)> = Vec::new();
+        for i in 0..total {
+            let k = if level >= 2 && i % 5 == 4 {
+                EnemyKind::Heavy
+            } else if level >= 1 && i % 3 == 2 {
+                EnemyKind::Scout
+            } else {
+                kind
+            };
+            match enemies.iter_mut().find(|(e, _)| *e == k) {
+                Some((_, n)) => *n += 1,
+                None => enemies.push((k, 1)),
+            }
+        }
+        Wave { enemies }
+    }
+}
+
+impl Waves {
+    /// Generated waves that never end
+    pub fn endless(base: usize, kind: EnemyKind) -> Waves {
+        Waves {
+            levels: Vec::new(),
+            base,
+            kind,
+            waves_per_level: 3,
+            level: 0,
+            wave: 0,
+            pending: Vec::new(),
+            spawn_delay: 60,
+            timer: 0,
+        }
+    }
+
+    /// Campaign file: `{"levels": [{"map": "...", "waves": [{"grunt": 3}, ...]}, ...]}`
+    pub fn load(path: &str) -> Result<Waves, String> {
+        let data =
+            std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+        let v: Value = serde_json::from_str(&data).map_err(|e| format!("{}: {}", path, e))?;
+        Waves::from_value(&v)
+    }
+
+    pub fn from_value(v: &Value) -> Result<Waves, String> {
+        let kind = v["kind"].as_str().unwrap_or("grunt");
+        let mut waves = Waves::endless(
+            v["base"].as_u64().unwrap_or(1) as usize,
+            EnemyKind::from_name(kind).ok_or_else(|| format!("unknown enemy kind {}", kind))?,
+        );
+        for l in v["levels"].as_array().into_iter().flatten() {
+            let level = Level {
+                map: l["map"].as_str().map(|s| s.to_string()),
+                waves: l["waves"]
+                    .as_array()
+                    .ok_or("a level needs waves")?
+                    .iter()
+                    .map(Wave::from_value)
+                    .collect::<Result<Vec<Wave>, String>>()?,
+            };
+            if level.waves.is_empty() {
+                return Err("a level needs waves".to_string());
+            }
+            waves.levels.push(level);
+        }
+        Ok(waves)
+    }
+
+    pub fn to_value(&self) -> Value {
+        let levels = self
+            .levels
+            .iter()
+            .map(|l| {
+                let waves = l.waves.iter().map(|w| w.to_value()).collect::<Vec<Value>>();
+                json!({ "map": l.map, "waves": waves })
+            })
+            .collect::<Vec<Value>>();
+        json!({ "base": self.base, "kind": self.kind.name(), "levels": levels })
+    }
+
+    pub fn is_endless(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    pub fn waves_in_level(&self) -> usize {
+        match self.levels.get(self.level) {
+            Some(level) => level.waves.len(),
+            None => self.waves_per_level,
+        }
+    }
+
+    pub fn current_wave(&self) -> Wave {
+        match self.levels.get(self.level) {
+            Some(level) => level.waves[self.wave].clone(),
+            None => Wave::generate(self.base, self.kind, self.level, self.wave),
+        }
+    }
+
+    /// Map of the current level, if it has one
+    pub fn current_map(&self) -> Option<&str> {
+        self.levels.get(self.level).and_then(|l| l.map.as_deref())
+    }
+
+    /// Health multiplier of the enemies
+    pub fn difficulty(&self) -> f64 {
+        1.0 + 0.15 * self.level as f64 + 0.05 * self.wave as f64
+    }
+
+    /// Queue the enemies of the current wave (mixed kinds, first one right away)
+    pub fn start_wave(&mut self) {
+        let wave = self.current_wave();
+        self.pending.clear();
+        let most = wave.enemies.iter().map(|(_, n)| *n).max().unwrap_or(0);
+        for i in 0..most {
+            for kind in KINDS {
+                let count = wave
+                    .enemies
+                    .iter()
+                    .filter(|(k, _)| *k == kind)
+                    .map(|(_, n)| *n)
+                    .sum::<usize>();
+                if i < count {
+                    self.pending.push(kind);
+                }
+            }
+        }
+        self.pending.reverse(); // popped from the end
+        self.timer = 0;
+    }
+
+    /// Enemy to spawn this frame, if any
+    pub fn update(&mut self) -> Option<EnemyKind> {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return None;
+        }
+        let kind = self.pending.pop()?;
+        self.timer = self.spawn_delay;
+        Some(kind)
+    }
+
+    /// Whether all the enemies of the wave were spawned and killed
+    pub fn is_wave_cleared(&self, alive: usize) -> bool {
+        self.pending.is_empty() && alive == 0
+    }
+
+    /// Go to the next wave of the level, false if the level is over
+    pub fn next_wave(&mut self) -> bool {
+        if self.wave + 1 >= self.waves_in_level() {
+            return false;
+        }
+        self.wave += 1;
+        self.start_wave();
+        true
+    }
+
+    /// Go to the next level, false if the campaign is over
+    pub fn next_level(&mut self) -> bool {
+        if !self.is_endless() && self.level + 1 >= self.levels.len() {
+            return false;
+        }
+        self.level += 1;
+        self.wave = 0;
+        self.start_wave();
+        true
+    }
+}
@@ -0,0 +1,198 @@
+use serde_json::{json, Map as JsonMap, Value};
+
+use crate::{player::Player, projectile::ProjectileKind, watch::Watch, PI};
+
+/// Stats of a weapon, from the catalogue
+#[derive(Clone, Debug, PartialEq)]
+pub struct Weapon {
+    pub name: String,
+    pub damage: f64,      // damage of one bullet
+    pub range: f64,       // maximum distance of a shot
+    pub mag_size: u64,    // size of the magazine
+    pub fire_rate: u64,   // frames between shots
+    pub reload_time: u64, // frames to reload
+    pub min_spread: f64,  // smallest cone of fire (radians, `min_acc` of the player)
+    pub max_spread: f64,  // largest cone of fire (radians, `max_acc` of the player)
+    pub spread_rec: f64,  // cone of fire change per frame (radians, `accuracy_rec` of the player)
+    pub pellets: u64,     // bullets per shot
+    pub penetration: f64, // multiplier of the damage kept when going through walls
+
+    pub projectile: Option<ProjectileKind>, // fired instead of bullets (none for hitscan)
+}
+
+/// All the weapons, loaded from a data file that is reloaded when it changes
+#[derive(Clone, Debug, Default)]
+pub struct Catalogue {
+    pub weapons: Vec<Weapon>,
+    watch: Option<Watch>, // file the catalogue comes from
+}
+
+impl Weapon {
+    /// `{"damage": 20, "range": 400, ...}`, spreads are in degrees
+    pub fn from_value(name: &str, v: &Value) -> Result<Weapon, String> {
+        let number = |key: &str| {
+            v[key]
+                .as_f64()
+                .ok_or_else(|| format!("{}: missing or invalid {}", name, key))
+        };
+        let count = |key: &str| {
+            v[key]
+                .as_u64()
+                .ok_or_else(|| format!("{}: missing or invalid {}", name, key))
+        };
+        let projectile = match v["projectile"].as_str() {
+            Some(kind) => Some(
+                ProjectileKind::from_name(kind)
+                    .ok_or_else(|| format!("{}: unknown projectile {}", name, kind))?,
+            ),
+            None => None,
+        };
+        let weapon = Weapon {
+            name: name.to_string(),
+            damage: number("damage")?,
+            range: number("range")?,
+            mag_size: count("mag_size")?,
+            fire_rate: count("fire_rate")?,
+            reload_time: count("reload_time")?,
+            min_spread: number("min_spread")?.to_radians(),
+            max_spread: number("max_spread")?.to_radians(),
+            spread_rec: number("spread_recovery")?.to_radians(),
+            pellets: v["pellets"].as_u64().unwrap_or(1),
+            penetration: v["penetration"].as_f64().unwrap_or(1.0),
+            projectile,
+        };
+        weapon.validate()?;
+        Ok(weapon)
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut v = json!({
+            "damage": self.damage,
+            "range": self.range,
+            "mag_size": self.mag_size,
+            "fire_rate": self.fire_rate,
+            "reload_time": self.reload_time,
+            "min_spread": self.min_spread.to_degrees(),
+            "max_spread": self.max_spread.to_degrees(),
+            "spread_recovery": self.spread_rec.to_degrees(),
+            "pellets": self.pellets,
+            "penetration": self.penetration,
+        });
+        if let Some(kind) = self.projectile {
+            v["projectile"] = json!(kind.name());
+        }
+        v
+    }
+
+    /// Checks that the stats make sense
+    pub fn validate(&self) -> Result<(), String> {
+        let err = |msg: &str| Err(format!("{}: {}", self.name, msg));
+        if self.damage <= 0.0 || self.range <= 0.0 {
+            return err("damage and range must be positive");
+        }
+        if self.mag_size == 0 || self.pellets == 0 {
+            return err("mag_size and pellets must be at least 1");
+        }
+        if self.fire_rate == 0 {
+            return err("fire_rate must be at least 1 frame");
+        }
+        if self.min_spread < 0.0 || self.min_spread > self.max_spread || self.max_spread > PI / 2.0
+        {
+            return err("spreads must be such that 0 <= min_spread <= max_spread <= 90");
+        }
+        if self.spread_rec < 0.0 {
+            return err("spread_recovery must not be negative");
+        }
+        if self.penetration < 0.0 {
+            return err("penetration must not be negative");
+        }
+        Ok(())
+    }
+
+    /// Gives the weapon to a player, switching from another one puts its magazine back in the reserve
+    pub fn apply(&self, player: &mut Player) {
+        if player.weapon != self.name {
+            player.weapon = self.name.clone();
+            player.ammo += player.in_mag;
+            player.in_mag = u64::min(self.mag_size, player.ammo);
+            player.ammo -= player.in_mag;
+            player.fire_cooldown = 0;
+            player.reload_cooldown = 0;
+        } else if player.in_mag > self.mag_size {
+            player.ammo += player.in_mag - self.mag_size;
+            player.in_mag = self.mag_size;
+        }
+        player.damage = self.damage;
+        player.range = self.range;
+        player.mag_size = self.mag_size;
+        player.fire_rate = self.fire_rate;
+        player.reload_time = self.reload_time;
+        player.min_acc = self.min_spread;
+        player.max_acc = self.max_spread;
+        player.accuracy_rec = self.spread_rec;
+        player.accuracy = player.accuracy.clamp(self.min_spread, self.max_spread);
+        player.pellets = self.pellets;
+        player.penetration = self.penetration;
+        player.projectile = self.projectile;
+    }
+}
+
+impl Catalogue {
+    pub fn new() -> Catalogue {
+        Catalogue::default()
+    }
+
+    /// Catalogue file: `{"rifle": {"damage": 20, ...}, ...}`, watched for changes
+    pub fn load(path: &str) -> Result<Catalogue, String> {
+        let watch = Watch::new(path);
+        let data =
+            std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+        let v: Value = serde_json::from_str(&data).map_err(|e| format!("{}: {}", path, e))?;
+        let mut catalogue = Catalogue::from_value(&v).map_err(|e| format!("{}: {}", path, e))?;
+        catalogue.watch = Some(watch);
+        Ok(catalogue)
+    }
+
+    pub fn from_value(v: &Value) -> Result<Catalogue, String> {
+        let weapons = v
+            .as_object()
+            .ok_or("the weapons must be an object")?
+            .iter()
+            .map(|(name, w)| Weapon::from_value(name, w))
+            .collect::<Result<Vec<Weapon>, String>>()?;
+        if weapons.is_empty() {
+            return Err("no weapons".to_string());
+        }
+        Ok(Catalogue {
+            weapons,
+            watch: None,
+        })
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut v = JsonMap::new();
+        for w in self.weapons.iter() {
+            v.insert(w.name.clone(), w.to_value());
+        }
+        Value::Object(v)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Weapon> {
+        self.weapons.iter().find(|w| w.name == name)
+    }
+
+    /// Loads the file again if it changed, true if it was reloaded
+    /// (the catalogue is kept as it is when the new one is invalid)
+    pub fn reload(&mut self) -> Result<bool, String> {
+        let watch = match self.watch.as_mut() {
+            Some(watch) => watch,
+            None => return Ok(false),
+        };
+        if !watch.changed() {
+            return Ok(false);
+        }
+        let path = watch.path.clone();
+        self.weapons = Catalogue::load(&path)?.weapons;
+        Ok(true)
+    }
+}